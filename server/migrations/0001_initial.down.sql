@@ -0,0 +1,3 @@
+DROP TABLE notes;
+DROP TABLE categories;
+DROP TABLE users;
@@ -1,7 +1,5 @@
-DROP TABLE IF EXISTS notes;
-DROP TABLE IF EXISTS categories;
-DROP TABLE IF EXISTS users;
-
+-- the schema as it was before migrations were introduced, existing
+-- databases already have these tables
 CREATE TABLE IF NOT EXISTS users (
     id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
     created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
@@ -0,0 +1,4 @@
+CREATE INDEX notes_search_idx ON notes USING GIN (
+    (setweight(to_tsvector('english', title), 'A') ||
+     setweight(to_tsvector('english', content), 'B'))
+);
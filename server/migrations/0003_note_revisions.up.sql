@@ -0,0 +1,14 @@
+CREATE TABLE note_revisions (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    note_id BIGINT NOT NULL REFERENCES notes ON DELETE CASCADE,
+    user_id BIGINT NOT NULL REFERENCES users,
+
+    revision INTEGER NOT NULL,
+
+    title TEXT NOT NULL,
+    content TEXT NOT NULL,
+
+    UNIQUE (note_id, revision)
+);
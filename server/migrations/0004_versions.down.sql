@@ -0,0 +1,7 @@
+ALTER TABLE notes
+    DROP COLUMN version,
+    DROP COLUMN updated_at;
+
+ALTER TABLE categories
+    DROP COLUMN version,
+    DROP COLUMN updated_at;
@@ -0,0 +1,19 @@
+CREATE TABLE tags (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    user_id BIGINT NOT NULL REFERENCES users,
+
+    title TEXT NOT NULL,
+
+    UNIQUE (user_id, title)
+);
+
+CREATE TABLE note_tags (
+    note_id BIGINT NOT NULL REFERENCES notes ON DELETE CASCADE,
+    tag_id BIGINT NOT NULL REFERENCES tags ON DELETE CASCADE,
+
+    PRIMARY KEY (note_id, tag_id)
+);
+
+CREATE INDEX note_tags_tag_idx ON note_tags (tag_id);
@@ -0,0 +1,5 @@
+DROP TABLE triage_rules;
+DROP TYPE triage_rule_kind;
+
+ALTER TABLE notes
+    DROP COLUMN bookmarked;
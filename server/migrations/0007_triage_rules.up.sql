@@ -0,0 +1,19 @@
+-- created via the bookmark endpoint
+ALTER TABLE notes
+    ADD COLUMN bookmarked BOOLEAN NOT NULL DEFAULT false;
+
+CREATE TYPE triage_rule_kind AS ENUM ('title_regex', 'url_domain', 'bookmark');
+
+CREATE TABLE triage_rules (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    user_id BIGINT NOT NULL REFERENCES users,
+    category_id BIGINT NOT NULL REFERENCES categories ON DELETE CASCADE,
+
+    -- rules are tried in ascending order of priority
+    priority INTEGER NOT NULL DEFAULT 0,
+    kind triage_rule_kind NOT NULL,
+    pattern TEXT NOT NULL DEFAULT '',
+    enabled BOOLEAN NOT NULL DEFAULT true
+);
@@ -0,0 +1,3 @@
+-- days before binned notes are purged, null uses the server's default
+ALTER TABLE users
+    ADD COLUMN bin_retention_days INTEGER;
@@ -0,0 +1,11 @@
+CREATE TABLE invites (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_by BIGINT NOT NULL REFERENCES users,
+
+    code TEXT UNIQUE NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    used_at TIMESTAMPTZ,
+    used_by BIGINT REFERENCES users
+);
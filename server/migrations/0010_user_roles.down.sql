@@ -0,0 +1,5 @@
+ALTER TABLE users
+    DROP COLUMN role,
+    DROP COLUMN disabled;
+
+DROP TYPE user_role;
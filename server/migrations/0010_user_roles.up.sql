@@ -0,0 +1,8 @@
+CREATE TYPE user_role AS ENUM ('user', 'admin');
+
+ALTER TABLE users
+    ADD COLUMN role user_role NOT NULL DEFAULT 'user',
+    ADD COLUMN disabled BOOLEAN NOT NULL DEFAULT false;
+
+-- the admin used to be whoever had the id 1
+UPDATE users SET role = 'admin' WHERE id = 1;
@@ -0,0 +1,12 @@
+CREATE TABLE password_resets (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    user_id BIGINT NOT NULL REFERENCES users,
+
+    -- sha256 of the token, the token itself is only ever in the mail
+    token_hash TEXT UNIQUE NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+
+    used_at TIMESTAMPTZ
+);
@@ -0,0 +1,15 @@
+CREATE TABLE sessions (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    user_id BIGINT NOT NULL REFERENCES users,
+
+    -- sha256 of the token held in the session cookie
+    token_hash TEXT UNIQUE NOT NULL,
+
+    user_agent TEXT,
+    ip_address TEXT,
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX sessions_user_id_idx ON sessions(user_id);
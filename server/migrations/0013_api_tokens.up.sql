@@ -0,0 +1,16 @@
+CREATE TYPE api_token_scope AS ENUM ('read', 'read_write', 'bookmark');
+
+CREATE TABLE api_tokens (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    user_id BIGINT NOT NULL REFERENCES users,
+
+    name TEXT NOT NULL,
+    scope api_token_scope NOT NULL,
+
+    -- sha256 of the token, the token itself is only shown once on creation
+    token_hash TEXT UNIQUE NOT NULL,
+
+    last_used_at TIMESTAMPTZ
+);
@@ -0,0 +1,15 @@
+-- audit trail of failed and locked out login attempts
+CREATE TABLE failed_logins (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    email TEXT NOT NULL,
+    -- null if the email didn't belong to a user
+    user_id BIGINT REFERENCES users,
+    ip_address TEXT,
+
+    -- true if the attempt was rejected without checking the password
+    locked_out BOOLEAN NOT NULL
+);
+
+CREATE INDEX failed_logins_email_idx ON failed_logins (email, created_at);
@@ -0,0 +1,19 @@
+-- TOTP 2FA, the secret is set when enrolling and only enabled once a code
+-- from it has been verified. totp_last_step is the time step of the last
+-- accepted code, codes can't be used twice
+ALTER TABLE users
+    ADD COLUMN totp_secret BYTEA,
+    ADD COLUMN totp_enabled BOOLEAN NOT NULL DEFAULT false,
+    ADD COLUMN totp_last_step BIGINT;
+
+CREATE TABLE recovery_codes (
+    id BIGINT GENERATED BY DEFAULT AS IDENTITY PRIMARY KEY,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+
+    user_id BIGINT NOT NULL REFERENCES users,
+
+    -- sha256 of the code
+    code_hash TEXT NOT NULL,
+
+    used_at TIMESTAMPTZ
+);
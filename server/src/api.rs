@@ -15,42 +15,104 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::handler::admin;
 use crate::handler::bin;
 use crate::handler::categories;
 use crate::handler::notes;
+use crate::handler::revisions;
+use crate::handler::rules;
+use crate::handler::tags;
+use crate::handler::totp;
 use crate::handler::triaged;
 use crate::handler::users;
 
+use crate::error::{Error, Result};
+
 use actix_files::NamedFile;
 use actix_web::dev;
+use actix_web::error::{JsonPayloadError, PathError, QueryPayloadError};
 use actix_web::middleware::errhandlers::ErrorHandlerResponse;
-use actix_web::web::{delete, get, post, put, scope};
+use actix_web::web::{delete, get, post, put, route, scope};
+use actix_web::{HttpRequest, HttpResponse};
 use tracing::warn;
 
+pub const MOUNT_POINT: &str = "/api";
+
+// relative to the mount point, this is the only route that a bookmark
+// scoped api token can reach
+//
+pub const BOOKMARK_PATH: &str = "/notes/bookmark";
+
 pub fn public_api(mount_point: &str) -> actix_web::Scope {
     scope(mount_point)
+        // anything under the mount point that isn't a route gets a json 404
+        // rather than falling through to the static files
+        .default_service(route().to(unknown_route))
         // login/logout
         .service(
             scope("/auth")
                 .route("", post().to(users::login))
-                .route("", delete().to(users::logout)),
+                .route("", delete().to(users::logout))
+                .route("/2fa", post().to(totp::login)),
         )
         // registration
         .service(
             scope("/users")
                 .route("", post().to(users::create_user))
-                .route("", get().to(users::get_user)),
+                .route("", get().to(users::get_user))
+                .route("", delete().to(users::delete_user))
+                .route("/export", get().to(users::export))
+                .route("/bin-retention", get().to(users::get_bin_retention))
+                .route("/bin-retention", put().to(users::set_bin_retention))
+                .route("/password", put().to(users::change_password))
+                .route("/sessions", get().to(users::get_sessions))
+                .route("/sessions", delete().to(users::delete_all_sessions))
+                .route("/sessions/{id}", delete().to(users::delete_session))
+                .route("/tokens", post().to(users::create_token))
+                .route("/tokens", get().to(users::get_tokens))
+                .route("/tokens/{id}", delete().to(users::delete_token))
+                .route("/2fa", post().to(totp::enrol))
+                .route("/2fa", delete().to(totp::disable))
+                .route("/2fa/verify", post().to(totp::verify_enrolment))
+                .route("/password-reset", post().to(users::request_password_reset))
+                .route(
+                    "/password-reset/confirm",
+                    post().to(users::confirm_password_reset),
+                ),
+        )
+        // admin
+        .service(
+            scope("/admin")
+                .route("/invites", post().to(admin::create_invite))
+                .route("/invites", get().to(admin::get_invites))
+                .route("/invites/{id}", delete().to(admin::delete_invite))
+                .route("/users", get().to(admin::get_users))
+                .route("/users/{id}/disable", post().to(admin::disable_user))
+                .route("/users/{id}/enable", post().to(admin::enable_user))
+                .route("/users/{id}/password", post().to(admin::reset_password)),
         )
-        // notes
+        // notes, the bookmark route comes before the notes scope so that
+        // it's matched first
+        .route(BOOKMARK_PATH, post().to(notes::bookmark))
         .service(
             scope("/notes")
-                .route("/bookmark", post().to(notes::bookmark))
+                .route("/search", get().to(notes::search))
+                .route("/bulk", post().to(notes::bulk))
                 .route("", post().to(notes::create))
                 .route("", get().to(notes::get_all))
                 .route("/{id}", get().to(notes::get))
                 .route("/{id}", put().to(notes::edit))
                 .route("/{id}/triage", post().to(notes::triage))
-                .route("/{id}/bin", post().to(notes::bin)),
+                .route("/{id}/bin", post().to(notes::bin))
+                .route("/{id}/revisions", get().to(revisions::get_all))
+                .route("/{id}/revisions/{rev}", get().to(revisions::get))
+                .route("/{id}/revisions/{rev}/diff", get().to(revisions::diff))
+                .route(
+                    "/{id}/revisions/{rev}/restore",
+                    post().to(revisions::restore),
+                )
+                .route("/{id}/tags/{tag_id}", post().to(tags::add_to_note))
+                .route("/{id}/tags/{tag_id}", delete().to(tags::remove_from_note)),
         )
         // triaged notes
         .service(
@@ -76,11 +138,64 @@ pub fn public_api(mount_point: &str) -> actix_web::Scope {
                 .route("", get().to(categories::get_all))
                 .route("/{id}", get().to(categories::get))
                 .route("/{id}", put().to(categories::edit))
-                .route("/{id}", delete().to(categories::delete)),
+                .route("/{id}", delete().to(categories::delete))
+                .route("/{id}/move", post().to(categories::move_to))
+                .route("/{id}/notes", get().to(categories::get_notes)),
         )
+        // triage rules
+        .service(
+            scope("/rules")
+                .route("/apply", post().to(rules::apply))
+                .route("", post().to(rules::create))
+                .route("", get().to(rules::get_all))
+                .route("/{id}", get().to(rules::get))
+                .route("/{id}", put().to(rules::edit))
+                .route("/{id}", delete().to(rules::delete)),
+        )
+        // tags
+        .service(
+            scope("/tags")
+                .route("", post().to(tags::create))
+                .route("", get().to(tags::get_all))
+                .route("/{id}", get().to(tags::get))
+                .route("/{id}", put().to(tags::edit))
+                .route("/{id}", delete().to(tags::delete)),
+        )
+}
+
+async fn unknown_route() -> Result<HttpResponse> {
+    Err(Error::NotFound)
+}
+
+// extractor failures are reported in the same json format as the handlers' errors
+//
+pub fn json_error(err: JsonPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    match err {
+        JsonPayloadError::Overflow => Error::PayloadTooLarge.into(),
+        err => Error::InvalidRequest(err.to_string()).into(),
+    }
+}
+
+pub fn query_error(err: QueryPayloadError, _req: &HttpRequest) -> actix_web::Error {
+    Error::InvalidRequest(err.to_string()).into()
+}
+
+pub fn path_error(err: PathError, _req: &HttpRequest) -> actix_web::Error {
+    Error::InvalidRequest(err.to_string()).into()
+}
+
+// the html error pages are only for browsers, api responses already carry a
+// json description of the error
+//
+fn is_api<B>(res: &dev::ServiceResponse<B>) -> bool {
+    let path = res.request().path();
+    path == MOUNT_POINT || path.starts_with(&format!("{}/", MOUNT_POINT))
 }
 
 pub fn bad_request<B>(res: dev::ServiceResponse<B>) -> actix_web::Result<ErrorHandlerResponse<B>> {
+    if is_api(&res) {
+        return Ok(ErrorHandlerResponse::Response(res));
+    }
     let new_resp = NamedFile::open("errors/400.html")?
         .set_status_code(res.status())
         .into_response(res.request())?;
@@ -91,6 +206,9 @@ pub fn bad_request<B>(res: dev::ServiceResponse<B>) -> actix_web::Result<ErrorHa
 }
 
 pub fn not_found<B>(res: dev::ServiceResponse<B>) -> actix_web::Result<ErrorHandlerResponse<B>> {
+    if is_api(&res) {
+        return Ok(ErrorHandlerResponse::Response(res));
+    }
     let new_resp = NamedFile::open("errors/404.html")?
         .set_status_code(res.status())
         .into_response(res.request())?;
@@ -103,6 +221,9 @@ pub fn not_found<B>(res: dev::ServiceResponse<B>) -> actix_web::Result<ErrorHand
 pub fn internal_server_error<B>(
     res: dev::ServiceResponse<B>,
 ) -> actix_web::Result<ErrorHandlerResponse<B>> {
+    if is_api(&res) {
+        return Ok(ErrorHandlerResponse::Response(res));
+    }
     let new_resp = NamedFile::open("errors/500.html")?
         .set_status_code(res.status())
         .into_response(res.request())?;
@@ -0,0 +1,234 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// writes uncompressed ustar archives, just enough for exporting a user's
+// notes as regular files, and finds files in them again when importing
+//
+const BLOCK_SIZE: usize = 512;
+const NAME_SIZE: usize = 100;
+const PREFIX_SIZE: usize = 155;
+
+#[derive(Default)]
+pub struct Tar {
+    buf: Vec<u8>,
+}
+
+impl Tar {
+    pub fn new() -> Tar {
+        Default::default()
+    }
+
+    // paths that can't fit in a ustar header are shortened by keeping the
+    // start of the directory and the end of the filename
+    //
+    pub fn append(&mut self, path: &str, contents: &[u8], mtime: i64) {
+        let (prefix, name) = split_path(path);
+
+        let mut header = [0u8; BLOCK_SIZE];
+        write_field(&mut header[0..100], name.as_bytes());
+        write_octal(&mut header[100..108], 0o644);
+        write_octal(&mut header[108..116], 0);
+        write_octal(&mut header[116..124], 0);
+        write_octal(&mut header[124..136], contents.len() as u64);
+        write_octal(&mut header[136..148], mtime.max(0) as u64);
+        header[156] = b'0';
+        write_field(&mut header[257..263], b"ustar\0");
+        write_field(&mut header[263..265], b"00");
+        write_field(&mut header[345..500], prefix.as_bytes());
+
+        // the checksum is calculated with its own field filled with spaces
+        for b in header[148..156].iter_mut() {
+            *b = b' ';
+        }
+        let checksum: u32 = header.iter().map(|b| u32::from(*b)).sum();
+        write_field(
+            &mut header[148..156],
+            format!("{:06o}\0 ", checksum).as_bytes(),
+        );
+
+        self.buf.extend_from_slice(&header);
+        self.buf.extend_from_slice(contents);
+        let padding = (BLOCK_SIZE - contents.len() % BLOCK_SIZE) % BLOCK_SIZE;
+        self.buf.resize(self.buf.len() + padding, 0);
+    }
+
+    pub fn finish(mut self) -> Vec<u8> {
+        // two empty blocks mark the end of the archive
+        self.buf.resize(self.buf.len() + BLOCK_SIZE * 2, 0);
+        self.buf
+    }
+}
+
+// returns the contents of the file at path, or None if the archive doesn't
+// contain it or isn't a tar archive at all
+//
+pub fn find<'a>(archive: &'a [u8], path: &str) -> Option<&'a [u8]> {
+    let mut offset = 0;
+    while offset + BLOCK_SIZE <= archive.len() {
+        let header = &archive[offset..offset + BLOCK_SIZE];
+        if header.iter().all(|b| *b == 0) || &header[257..262] != b"ustar" {
+            return None;
+        }
+
+        let name = read_field(&header[0..100]);
+        let prefix = read_field(&header[345..500]);
+        let size = usize::from_str_radix(read_field(&header[124..136]).trim(), 8).ok()?;
+
+        let start = offset + BLOCK_SIZE;
+        let end = start.checked_add(size)?;
+        if end > archive.len() {
+            return None;
+        }
+
+        let matches = if prefix.is_empty() {
+            name == path
+        } else {
+            format!("{}/{}", prefix, name) == path
+        };
+        if matches {
+            return Some(&archive[start..end]);
+        }
+
+        offset = start + size + (BLOCK_SIZE - size % BLOCK_SIZE) % BLOCK_SIZE;
+    }
+
+    None
+}
+
+fn read_field(field: &[u8]) -> String {
+    let len = field.iter().position(|b| *b == 0).unwrap_or(field.len());
+    String::from_utf8_lossy(&field[..len]).into_owned()
+}
+
+fn write_field(field: &mut [u8], value: &[u8]) {
+    let len = value.len().min(field.len());
+    field[..len].copy_from_slice(&value[..len]);
+}
+
+fn write_octal(field: &mut [u8], value: u64) {
+    let digits = field.len() - 1;
+    write_field(
+        field,
+        format!("{:0width$o}\0", value, width = digits).as_bytes(),
+    );
+}
+
+fn truncate_start(s: &str, max: usize) -> &str {
+    let mut start = s.len().saturating_sub(max);
+    while !s.is_char_boundary(start) {
+        start += 1;
+    }
+    &s[start..]
+}
+
+fn truncate_end(s: &str, max: usize) -> &str {
+    let mut end = s.len().min(max);
+    while !s.is_char_boundary(end) {
+        end -= 1;
+    }
+    &s[..end]
+}
+
+fn split_path(path: &str) -> (&str, &str) {
+    if path.len() <= NAME_SIZE {
+        return ("", path);
+    }
+    match path.rfind('/') {
+        Some(slash) => (
+            truncate_end(&path[..slash], PREFIX_SIZE),
+            truncate_start(&path[slash + 1..], NAME_SIZE),
+        ),
+        None => ("", truncate_start(path, NAME_SIZE)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn field(header: &[u8], start: usize, end: usize) -> String {
+        String::from_utf8_lossy(&header[start..end])
+            .trim_end_matches('\0')
+            .to_string()
+    }
+
+    #[test]
+    fn test_layout() {
+        let mut tar = Tar::new();
+        tar.append("a/b.md", b"hello", 0);
+        tar.append("c.json", &[0; 512], 0);
+        let archive = tar.finish();
+
+        // header + padded contents for each file, then two end blocks
+        assert_eq!(archive.len(), 512 * 2 + 512 * 2 + 512 * 2);
+        assert_eq!(field(&archive, 0, 100), "a/b.md");
+        assert_eq!(field(&archive, 124, 136), "00000000005");
+        assert_eq!(&archive[512..517], b"hello");
+        assert_eq!(field(&archive, 1024, 1124), "c.json");
+        assert!(archive[2048..].iter().all(|b| *b == 0));
+    }
+
+    #[test]
+    fn test_checksum() {
+        let mut tar = Tar::new();
+        tar.append("note.md", b"content", 1600000000);
+        let archive = tar.finish();
+
+        let stored = u32::from_str_radix(field(&archive, 148, 154).as_str(), 8).unwrap();
+        let calculated: u32 = archive[..512]
+            .iter()
+            .enumerate()
+            .map(|(i, b)| {
+                if (148..156).contains(&i) {
+                    32
+                } else {
+                    u32::from(*b)
+                }
+            })
+            .sum();
+        assert_eq!(stored, calculated);
+    }
+
+    #[test]
+    fn test_long_paths() {
+        let dir = "d".repeat(120);
+        let name = "n".repeat(90);
+        let path = format!("{}/{}", dir, name);
+        assert_eq!(split_path(&path), (dir.as_str(), name.as_str()));
+
+        let long = format!("{}/{}", "d".repeat(200), "n".repeat(120));
+        let (prefix, name) = split_path(&long);
+        assert_eq!(prefix.len(), PREFIX_SIZE);
+        assert_eq!(name.len(), NAME_SIZE);
+    }
+
+    #[test]
+    fn test_find() {
+        let long = format!("{}/{}.md", "dir".repeat(40), "name".repeat(10));
+        let mut tar = Tar::new();
+        tar.append("a/b.md", b"hello", 0);
+        tar.append("c.json", &[1; 600], 0);
+        tar.append(&long, b"long", 0);
+        let archive = tar.finish();
+
+        assert_eq!(find(&archive, "a/b.md"), Some(&b"hello"[..]));
+        assert_eq!(find(&archive, "c.json").map(<[u8]>::len), Some(600));
+        assert_eq!(find(&archive, &long), Some(&b"long"[..]));
+        assert_eq!(find(&archive, "missing"), None);
+        assert_eq!(find(b"{\"not\": \"a tar\"}", "c.json"), None);
+    }
+}
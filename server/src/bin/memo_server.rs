@@ -15,9 +15,14 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use memo_server::{start_server, Result};
+use memo_server::run;
+use std::env;
+use std::process;
 
 #[actix_rt::main]
-async fn main() -> Result<()> {
-    start_server().await
+async fn main() {
+    if let Err(e) = run(env::args().skip(1).collect()).await {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
 }
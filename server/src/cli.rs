@@ -0,0 +1,493 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// the memo_server command line, running it without a command starts the
+// server. the other commands are for operators and use the same
+// configuration as the server
+//
+use crate::config::{Config, SIGNING_KEY_SIZE};
+use crate::db::export as db_export;
+use crate::db::notes as db_notes;
+use crate::db::users as db_users;
+use crate::error::{Error, Result};
+use crate::handler::random_token;
+use crate::handler::users::hash_password;
+use crate::interop::users::{PasswordReset, Registration, UserRole};
+use crate::validation::{Limits, Validate};
+use crate::{create_pool, export, migrations, start_server};
+use deadpool_postgres::Pool;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::str::FromStr;
+use tracing::Level;
+use tracing_subscriber::FmtSubscriber;
+
+const USAGE: &str = "usage: memo_server [command]
+
+commands:
+    serve                                  run the server (the default)
+    migrate status|up|down                 list, apply or revert database migrations
+    create-user --email <email> --username <username> [--admin]
+                                           create a user, the password is read from stdin
+    reset-password --user <email>          set a user's password, read from stdin
+    export --user <email> [--output <file>]
+                                           write a user's data as a tar archive
+    import --user <email> <file>           add the contents of an export to a user's data
+    purge-bin [--days <days>]              delete notes that have been in the bin too long
+    gen-signing-key                        print a new SESSION_SIGNING_KEY
+    check-config                           check the configuration and the database, then
+                                           print the configuration with secrets redacted
+    help                                   print this message";
+
+// options that don't take a value
+const SWITCHES: &[&str] = &["--admin"];
+
+#[derive(Debug, PartialEq)]
+enum Command {
+    Serve,
+    Migrate(Migrate),
+    CreateUser {
+        email: String,
+        username: String,
+        admin: bool,
+    },
+    ResetPassword {
+        email: String,
+    },
+    Export {
+        email: String,
+        output: Option<String>,
+    },
+    Import {
+        email: String,
+        input: String,
+    },
+    PurgeBin {
+        days: Option<i32>,
+    },
+    GenSigningKey,
+    CheckConfig,
+    Help,
+}
+
+#[derive(Debug, PartialEq)]
+enum Migrate {
+    // list the migrations and whether they've been applied
+    Status,
+    // apply every pending migration
+    Up,
+    // revert the most recently applied migration
+    Down,
+}
+
+impl FromStr for Migrate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Migrate> {
+        match s {
+            "status" => Ok(Migrate::Status),
+            "up" => Ok(Migrate::Up),
+            "down" => Ok(Migrate::Down),
+            _ => Err(usage(&format!("unknown migrate command: {}", s))),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Options {
+    values: HashMap<String, String>,
+    switches: HashSet<String>,
+    positional: Vec<String>,
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options> {
+        let mut options: Options = Default::default();
+
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if SWITCHES.contains(&arg.as_str()) {
+                options.switches.insert(arg.clone());
+            } else if arg.starts_with("--") {
+                match args.next() {
+                    Some(value) => options.values.insert(arg.clone(), value.clone()),
+                    None => return Err(usage(&format!("{} needs a value", arg))),
+                };
+            } else {
+                options.positional.push(arg.clone());
+            }
+        }
+
+        Ok(options)
+    }
+
+    fn value(&mut self, name: &str) -> Option<String> {
+        self.values.remove(name)
+    }
+
+    fn required(&mut self, name: &str) -> Result<String> {
+        self.value(name)
+            .ok_or_else(|| usage(&format!("{} is required", name)))
+    }
+
+    fn switch(&mut self, name: &str) -> bool {
+        self.switches.remove(name)
+    }
+
+    fn positional(&mut self, name: &str) -> Result<String> {
+        if self.positional.is_empty() {
+            Err(usage(&format!("{} is required", name)))
+        } else {
+            Ok(self.positional.remove(0))
+        }
+    }
+
+    // anything that a command didn't use is a mistake
+    //
+    fn finish(self) -> Result<()> {
+        let unused = self
+            .values
+            .keys()
+            .chain(self.switches.iter())
+            .chain(self.positional.iter())
+            .next();
+
+        match unused {
+            Some(arg) => Err(usage(&format!("unexpected argument: {}", arg))),
+            None => Ok(()),
+        }
+    }
+}
+
+fn usage(problem: &str) -> Error {
+    Error::Usage(format!("{}\n\n{}", problem, USAGE))
+}
+
+fn parse(args: &[String]) -> Result<Command> {
+    let (command, rest) = match args.split_first() {
+        Some((command, rest)) => (command.as_str(), rest),
+        None => return Ok(Command::Serve),
+    };
+
+    let mut options = Options::parse(rest)?;
+    let command = match command {
+        "serve" => Command::Serve,
+        "migrate" => Command::Migrate(Migrate::from_str(&options.positional("status|up|down")?)?),
+        "create-user" => Command::CreateUser {
+            email: options.required("--email")?,
+            username: options.required("--username")?,
+            admin: options.switch("--admin"),
+        },
+        "reset-password" => Command::ResetPassword {
+            email: options.required("--user")?,
+        },
+        "export" => Command::Export {
+            email: options.required("--user")?,
+            output: options.value("--output"),
+        },
+        "import" => Command::Import {
+            email: options.required("--user")?,
+            input: options.positional("file")?,
+        },
+        "purge-bin" => Command::PurgeBin {
+            days: match options.value("--days") {
+                // 0 would empty the whole bin
+                Some(days) => match days.parse::<i32>()? {
+                    days if days < 1 => return Err(usage("--days has to be at least 1")),
+                    days => Some(days),
+                },
+                None => None,
+            },
+        },
+        "gen-signing-key" => Command::GenSigningKey,
+        "check-config" => Command::CheckConfig,
+        "help" | "--help" | "-h" => Command::Help,
+        _ => return Err(usage(&format!("unknown command: {}", command))),
+    };
+    options.finish()?;
+
+    Ok(command)
+}
+
+pub async fn run(args: Vec<String>) -> Result<()> {
+    match parse(&args)? {
+        Command::Serve => start_server().await,
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::GenSigningKey => {
+            println!("{}", random_token(SIGNING_KEY_SIZE));
+            Ok(())
+        }
+        Command::CheckConfig => check_config(&init()?).await,
+        Command::Migrate(migrate) => run_migrate(&create_pool(&init()?)?, migrate).await,
+        Command::CreateUser {
+            email,
+            username,
+            admin,
+        } => {
+            let config = init()?;
+            create_user(
+                &create_pool(&config)?,
+                &config.limits(),
+                email,
+                username,
+                admin,
+            )
+            .await
+        }
+        Command::ResetPassword { email } => {
+            let config = init()?;
+            reset_password(&create_pool(&config)?, &config.limits(), &email).await
+        }
+        Command::Export { email, output } => {
+            export_user(&create_pool(&init()?)?, &email, output).await
+        }
+        Command::Import { email, input } => {
+            import_user(&create_pool(&init()?)?, &email, &input).await
+        }
+        Command::PurgeBin { days } => {
+            let config = init()?;
+            purge_bin(
+                &create_pool(&config)?,
+                days.unwrap_or(config.bin_retention_days),
+            )
+            .await
+        }
+    }
+}
+
+fn init() -> Result<Config> {
+    dotenv::dotenv().ok();
+
+    // stdout is kept for the command's output, e.g. an export
+    let subscriber = FmtSubscriber::builder()
+        .with_max_level(Level::INFO)
+        .with_writer(io::stderr)
+        .finish();
+
+    tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+
+    Config::load()
+}
+
+async fn run_migrate(db_pool: &Pool, migrate: Migrate) -> Result<()> {
+    match migrate {
+        Migrate::Status => {
+            for migration in migrations::status(db_pool).await? {
+                match migration.applied_at {
+                    Some(applied_at) => println!(
+                        "{:04} {} (applied {})",
+                        migration.version, migration.name, applied_at
+                    ),
+                    None => println!("{:04} {} (pending)", migration.version, migration.name),
+                }
+            }
+        }
+        Migrate::Up => {
+            let migrated = migrations::up(db_pool).await?;
+            println!("applied {} migration(s)", migrated.len());
+        }
+        Migrate::Down => match migrations::down(db_pool).await? {
+            Some(migration) => println!("reverted {:04} {}", migration.version, migration.name),
+            None => println!("no migrations to revert"),
+        },
+    }
+
+    Ok(())
+}
+
+async fn create_user(
+    db_pool: &Pool,
+    limits: &Limits,
+    email: String,
+    username: String,
+    admin: bool,
+) -> Result<()> {
+    let registration = Registration {
+        username,
+        email,
+        password: read_password()?,
+        magic_word: None,
+        invite_code: None,
+    };
+    registration.validate(limits).map_err(explain)?;
+    let hash = hash_password(&registration.password)?;
+
+    let (user_id, user) = db_users::create(db_pool, &registration, &hash).await?;
+    if admin && user.role != UserRole::Admin {
+        db_users::set_role(db_pool, user_id, UserRole::Admin).await?;
+    }
+
+    println!("created user {} with id {}", registration.email, user_id);
+    Ok(())
+}
+
+async fn reset_password(db_pool: &Pool, limits: &Limits, email: &str) -> Result<()> {
+    let user_id = db_users::id_by_email(db_pool, email).await?;
+    let reset = PasswordReset {
+        password: read_password()?,
+    };
+    reset.validate(limits).map_err(explain)?;
+    let hash = hash_password(&reset.password)?;
+
+    db_users::set_password(db_pool, user_id, &hash, true).await?;
+
+    println!(
+        "changed the password of {}, their sessions and api tokens have been revoked",
+        email
+    );
+    Ok(())
+}
+
+async fn export_user(db_pool: &Pool, email: &str, output: Option<String>) -> Result<()> {
+    let user_id = db_users::id_by_email(db_pool, email).await?;
+    let archive = export::archive(&db_export::all(db_pool, user_id).await?)?;
+
+    match output {
+        Some(path) => fs::write(path, archive)?,
+        None => io::stdout().write_all(&archive)?,
+    }
+
+    Ok(())
+}
+
+async fn import_user(db_pool: &Pool, email: &str, input: &str) -> Result<()> {
+    let user_id = db_users::id_by_email(db_pool, email).await?;
+    let export = export::read(&fs::read(input)?)?;
+
+    let summary = db_export::import(db_pool, user_id, &export).await?;
+
+    println!(
+        "imported {} categories, {} tags, {} rules, {} notes and {} revisions",
+        summary.categories, summary.tags, summary.rules, summary.notes, summary.revisions
+    );
+    Ok(())
+}
+
+// users' own retention periods still apply, days replaces the server's default
+//
+async fn purge_bin(db_pool: &Pool, days: i32) -> Result<()> {
+    let purged = db_notes::purge_expired_bin(db_pool, days).await?;
+
+    println!("purged {} notes from the bin", purged);
+    Ok(())
+}
+
+// validation errors are meant for api clients, spell them out for the operator
+//
+fn explain(e: Error) -> Error {
+    match e {
+        Error::Validation(problems) => Error::Usage(
+            problems
+                .iter()
+                .map(|p| format!("{}: {}", p.field, p.message))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        ),
+        e => e,
+    }
+}
+
+// passwords are read from stdin rather than taken as an argument so that
+// they don't end up in the shell's history
+//
+fn read_password() -> Result<String> {
+    eprint!("password: ");
+    let mut password = String::new();
+    io::stdin().read_line(&mut password)?;
+    let password = password.trim_end_matches(&['\r', '\n'][..]);
+
+    if password.is_empty() {
+        return Err(Error::Usage(String::from("the password can't be empty")));
+    }
+
+    Ok(String::from(password))
+}
+
+// the configuration has already been validated when it was loaded
+//
+async fn check_config(config: &Config) -> Result<()> {
+    println!("{}", config.redacted()?);
+
+    let db_pool = create_pool(config)?;
+    let _ = db_pool.get().await?;
+
+    let pending = migrations::pending(&db_pool).await?.len();
+    if pending > 0 && !config.auto_migrate {
+        return Err(Error::Configuration(format!(
+            "{} pending database migration(s), run `memo_server migrate up`",
+            pending
+        )));
+    }
+
+    println!("# database connected, {} pending migration(s)", pending);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(String::from).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(parse(&[]).unwrap(), Command::Serve);
+        assert_eq!(
+            parse(&args("migrate up")).unwrap(),
+            Command::Migrate(Migrate::Up)
+        );
+        assert_eq!(
+            parse(&args("create-user --admin --username indy --email a@b.c")).unwrap(),
+            Command::CreateUser {
+                email: String::from("a@b.c"),
+                username: String::from("indy"),
+                admin: true,
+            }
+        );
+        assert_eq!(
+            parse(&args("import --user a@b.c export.tar")).unwrap(),
+            Command::Import {
+                email: String::from("a@b.c"),
+                input: String::from("export.tar"),
+            }
+        );
+        assert_eq!(
+            parse(&args("purge-bin --days 7")).unwrap(),
+            Command::PurgeBin { days: Some(7) }
+        );
+    }
+
+    #[test]
+    fn test_parse_mistakes() {
+        assert!(parse(&args("frobnicate")).is_err());
+        assert!(parse(&args("migrate sideways")).is_err());
+        assert!(parse(&args("migrate")).is_err());
+        assert!(parse(&args("export")).is_err());
+        assert!(parse(&args("export --user")).is_err());
+        assert!(parse(&args("export --user a@b.c --colour red")).is_err());
+        assert!(parse(&args("gen-signing-key now")).is_err());
+        assert!(parse(&args("purge-bin --days soon")).is_err());
+        assert!(parse(&args("purge-bin --days 0")).is_err());
+        assert!(parse(&args("purge-bin --days -3")).is_err());
+    }
+}
@@ -0,0 +1,583 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// the server's settings are layered: defaults, then the TOML file named by
+// MEMO_CONFIG (memo.toml when it isn't set and the file exists), then
+// environment variables. every setting can be overridden by the environment
+// variable with the upper case version of its name, e.g. POSTGRES_HOST
+//
+use crate::error::{Error, Result};
+use crate::validation::{Limits, MAX_PASSWORD_LENGTH};
+use crate::RegistrationMode;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::net::{IpAddr, SocketAddr};
+use std::path::{Path, PathBuf};
+use toml::value::{Table, Value};
+
+pub const SIGNING_KEY_SIZE: usize = 32;
+
+const DEFAULT_CONFIG_PATH: &str = "memo.toml";
+const MAX_POOL_SIZE: usize = 1024;
+const MIN_METRICS_TOKEN_LENGTH: usize = 32;
+const REDACTED: &str = "<redacted>";
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MailTransport {
+    // prints each mail, useful during development
+    Stdout,
+    // writes each mail into mail_dir
+    File,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // the port is used for every one of the addresses, with TLS configured
+    // they all only accept https
+    pub bind_addresses: Vec<String>,
+    pub port: u16,
+    pub tls_certificate: Option<PathBuf>,
+    pub tls_private_key: Option<PathBuf>,
+    // plain http for a reverse proxy on the same machine
+    pub unix_socket: Option<PathBuf>,
+    // take the client's address from the Forwarded and X-Forwarded-For
+    // headers rather than the connection, only safe behind a reverse proxy
+    // that overwrites them
+    pub trust_proxy_headers: bool,
+    pub www_path: PathBuf,
+
+    pub registration_mode: RegistrationMode,
+    pub registration_magic_word: String,
+    pub bin_retention_days: i32,
+
+    // limits on what clients can store
+    pub max_title_length: usize,
+    // in bytes
+    pub max_content_size: usize,
+    pub min_password_length: usize,
+    // how many of lowercase, uppercase, digits and symbols a password needs
+    pub password_character_classes: usize,
+
+    pub mail_transport: MailTransport,
+    pub mail_dir: Option<PathBuf>,
+    pub mail_from: String,
+
+    pub postgres_host: String,
+    pub postgres_port: u16,
+    pub postgres_db: String,
+    pub postgres_user: String,
+    pub postgres_password: String,
+    pub postgres_pool_size: usize,
+    // how long a request waits for a free connection before giving up,
+    // 0 waits forever
+    pub postgres_pool_timeout_secs: u64,
+    pub auto_migrate: bool,
+
+    // if set, a cookie will only be transmitted when the connection is secure.
+    // defaults to whether TLS is configured, a reverse proxy that terminates
+    // TLS needs it set explicitly
+    pub cookie_over_https_only: Option<bool>,
+    // 32 bytes as 64 hex digits
+    pub session_signing_key: String,
+    // bearer token that scrapes of /metrics have to present, the endpoint
+    // is off while it's empty
+    pub metrics_token: String,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        let limits = Limits::default();
+        Config {
+            bind_addresses: vec![String::from("127.0.0.1")],
+            port: 3004,
+            tls_certificate: None,
+            tls_private_key: None,
+            unix_socket: None,
+            trust_proxy_headers: false,
+            www_path: PathBuf::from("www"),
+            registration_mode: RegistrationMode::MagicWord,
+            registration_magic_word: String::new(),
+            bin_retention_days: 30,
+            max_title_length: limits.max_title_length,
+            max_content_size: limits.max_content_size,
+            min_password_length: limits.min_password_length,
+            password_character_classes: limits.password_character_classes,
+            mail_transport: MailTransport::Stdout,
+            mail_dir: None,
+            mail_from: String::from("memo@localhost"),
+            postgres_host: String::from("localhost"),
+            postgres_port: 5432,
+            postgres_db: String::from("memo"),
+            postgres_user: String::from("postgres"),
+            postgres_password: String::new(),
+            postgres_pool_size: 16,
+            postgres_pool_timeout_secs: 5,
+            auto_migrate: false,
+            cookie_over_https_only: None,
+            session_signing_key: String::new(),
+            metrics_token: String::new(),
+        }
+    }
+}
+
+// how an environment variable is turned into a TOML value for each setting
+//
+#[derive(Clone, Copy)]
+enum Kind {
+    Text,
+    // comma separated
+    List,
+    Integer,
+    Boolean,
+}
+
+const SETTINGS: &[(&str, Kind)] = &[
+    ("bind_addresses", Kind::List),
+    ("port", Kind::Integer),
+    ("tls_certificate", Kind::Text),
+    ("tls_private_key", Kind::Text),
+    ("unix_socket", Kind::Text),
+    ("trust_proxy_headers", Kind::Boolean),
+    ("www_path", Kind::Text),
+    ("registration_mode", Kind::Text),
+    ("registration_magic_word", Kind::Text),
+    ("bin_retention_days", Kind::Integer),
+    ("max_title_length", Kind::Integer),
+    ("max_content_size", Kind::Integer),
+    ("min_password_length", Kind::Integer),
+    ("password_character_classes", Kind::Integer),
+    ("mail_transport", Kind::Text),
+    ("mail_dir", Kind::Text),
+    ("mail_from", Kind::Text),
+    ("postgres_host", Kind::Text),
+    ("postgres_port", Kind::Integer),
+    ("postgres_db", Kind::Text),
+    ("postgres_user", Kind::Text),
+    ("postgres_password", Kind::Text),
+    ("postgres_pool_size", Kind::Integer),
+    ("postgres_pool_timeout_secs", Kind::Integer),
+    ("auto_migrate", Kind::Boolean),
+    ("cookie_over_https_only", Kind::Boolean),
+    ("session_signing_key", Kind::Text),
+    ("metrics_token", Kind::Text),
+];
+
+impl Config {
+    // fails with every problem that was found rather than just the first
+    //
+    pub fn load() -> Result<Config> {
+        let table = match env::var("MEMO_CONFIG") {
+            Ok(path) => read_table(Path::new(&path))?,
+            Err(_) if Path::new(DEFAULT_CONFIG_PATH).exists() => {
+                read_table(Path::new(DEFAULT_CONFIG_PATH))?
+            }
+            Err(_) => Table::new(),
+        };
+
+        let config = from_layers(table, |key| env::var(key).ok())?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    pub fn signing_key(&self) -> Result<[u8; SIGNING_KEY_SIZE]> {
+        parse_signing_key(&self.session_signing_key)
+    }
+
+    pub fn socket_addresses(&self) -> Result<Vec<SocketAddr>> {
+        self.bind_addresses
+            .iter()
+            .map(|address| match address.trim().parse::<IpAddr>() {
+                Ok(ip) => Ok(SocketAddr::new(ip, self.port)),
+                Err(_) => Err(Error::Configuration(format!(
+                    "bind_addresses: {} is not an IPv4 or IPv6 address",
+                    address
+                ))),
+            })
+            .collect()
+    }
+
+    pub fn tls_enabled(&self) -> bool {
+        self.tls_certificate.is_some() && self.tls_private_key.is_some()
+    }
+
+    pub fn limits(&self) -> Limits {
+        Limits {
+            max_title_length: self.max_title_length,
+            max_content_size: self.max_content_size,
+            min_password_length: self.min_password_length,
+            password_character_classes: self.password_character_classes,
+        }
+    }
+
+    // json escaping can make a note's encoding up to twice the size of its
+    // content, the remainder is room for the other fields
+    //
+    pub fn json_limit(&self) -> usize {
+        self.max_content_size
+            .saturating_mul(2)
+            .saturating_add(64 * 1024)
+    }
+
+    pub fn secure_cookies(&self) -> bool {
+        self.cookie_over_https_only
+            .unwrap_or_else(|| self.tls_enabled())
+    }
+
+    // the effective configuration as TOML with the secrets hidden
+    //
+    pub fn redacted(&self) -> Result<String> {
+        let mut config = self.clone();
+        for secret in [
+            &mut config.registration_magic_word,
+            &mut config.postgres_password,
+            &mut config.session_signing_key,
+            &mut config.metrics_token,
+        ]
+        .iter_mut()
+        {
+            if !secret.is_empty() {
+                **secret = String::from(REDACTED);
+            }
+        }
+
+        toml::to_string(&config).map_err(|e| Error::Configuration(e.to_string()))
+    }
+
+    fn validate(&self) -> Result<()> {
+        let mut problems = vec![];
+
+        if let Err(Error::Configuration(problem)) = self.socket_addresses() {
+            problems.push(problem);
+        }
+        if self.bind_addresses.is_empty() && self.unix_socket.is_none() {
+            problems.push(String::from(
+                "bind_addresses: can only be empty when listening on a unix_socket",
+            ));
+        }
+        match (&self.tls_certificate, &self.tls_private_key) {
+            (Some(certificate), Some(private_key)) => {
+                for (name, path) in [
+                    ("tls_certificate", certificate),
+                    ("tls_private_key", private_key),
+                ]
+                .iter()
+                {
+                    if !path.is_file() {
+                        problems.push(format!("{}: {} is not a file", name, path.display()));
+                    }
+                }
+            }
+            (None, None) => {}
+            _ => problems.push(String::from(
+                "tls_certificate and tls_private_key: both are required for TLS",
+            )),
+        }
+        if let Some(socket) = &self.unix_socket {
+            match socket.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+                    problems.push(format!("unix_socket: {} is not a directory", dir.display()))
+                }
+                _ => {}
+            }
+        }
+        if !self.www_path.is_dir() {
+            problems.push(format!(
+                "www_path: {} is not a directory",
+                self.www_path.display()
+            ));
+        }
+        if self.registration_mode == RegistrationMode::MagicWord
+            && self.registration_magic_word.is_empty()
+        {
+            problems.push(String::from(
+                "registration_magic_word: required when registration_mode is magic_word",
+            ));
+        }
+        if !self.metrics_token.is_empty() && self.metrics_token.len() < MIN_METRICS_TOKEN_LENGTH {
+            problems.push(format!(
+                "metrics_token: has to be at least {} characters",
+                MIN_METRICS_TOKEN_LENGTH
+            ));
+        }
+        if self.bin_retention_days < 1 {
+            problems.push(String::from("bin_retention_days: has to be at least 1"));
+        }
+        if self.mail_transport == MailTransport::File {
+            // the directory is created if it doesn't exist yet
+            match &self.mail_dir {
+                Some(dir) if dir.exists() && !dir.is_dir() => {
+                    problems.push(format!("mail_dir: {} is not a directory", dir.display()))
+                }
+                Some(_) => {}
+                None => problems.push(String::from(
+                    "mail_dir: required when mail_transport is file",
+                )),
+            }
+        }
+        if self.max_title_length < 1 || self.max_content_size < 1 {
+            problems.push(String::from(
+                "max_title_length and max_content_size: have to be at least 1",
+            ));
+        }
+        if !(1..=MAX_PASSWORD_LENGTH).contains(&self.min_password_length) {
+            problems.push(format!(
+                "min_password_length: has to be between 1 and {}",
+                MAX_PASSWORD_LENGTH
+            ));
+        }
+        if !(1..=4).contains(&self.password_character_classes) {
+            problems.push(String::from(
+                "password_character_classes: has to be between 1 and 4",
+            ));
+        }
+        if self.postgres_host.is_empty() || self.postgres_db.is_empty() {
+            problems.push(String::from(
+                "postgres_host and postgres_db: can't be empty",
+            ));
+        }
+        if !(1..=MAX_POOL_SIZE).contains(&self.postgres_pool_size) {
+            problems.push(format!(
+                "postgres_pool_size: has to be between 1 and {}",
+                MAX_POOL_SIZE
+            ));
+        }
+        if let Err(Error::Configuration(problem)) = self.signing_key() {
+            problems.push(format!("session_signing_key: {}", problem));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Configuration(problems.join("\n")))
+        }
+    }
+}
+
+fn read_table(path: &Path) -> Result<Table> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| Error::Configuration(format!("unable to read {}: {}", path.display(), e)))?;
+
+    toml::from_str(&contents)
+        .map_err(|e| Error::Configuration(format!("{}: {}", path.display(), e)))
+}
+
+// the environment overrides the file, env is given the upper case name of a
+// setting and returns its value if it's set
+//
+fn from_layers<F>(mut table: Table, env: F) -> Result<Config>
+where
+    F: Fn(&str) -> Option<String>,
+{
+    let mut problems = vec![];
+
+    for (key, kind) in SETTINGS {
+        let name = key.to_uppercase();
+        if let Some(value) = env(&name) {
+            let value = match kind {
+                Kind::Text => Ok(Value::String(value)),
+                Kind::List => Ok(Value::Array(
+                    value
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|v| !v.is_empty())
+                        .map(|v| Value::String(String::from(v)))
+                        .collect(),
+                )),
+                Kind::Integer => value
+                    .trim()
+                    .parse::<i64>()
+                    .map(Value::Integer)
+                    .map_err(|_| ()),
+                Kind::Boolean => value
+                    .trim()
+                    .parse::<bool>()
+                    .map(Value::Boolean)
+                    .map_err(|_| ()),
+            };
+            match value {
+                Ok(value) => {
+                    table.insert(String::from(*key), value);
+                }
+                Err(()) => problems.push(format!("{}: invalid value", name)),
+            }
+        }
+    }
+
+    if !problems.is_empty() {
+        return Err(Error::Configuration(problems.join("\n")));
+    }
+
+    Value::Table(table)
+        .try_into()
+        .map_err(|e| Error::Configuration(e.to_string()))
+}
+
+fn parse_signing_key(hex: &str) -> Result<[u8; SIGNING_KEY_SIZE]> {
+    // 2 hex digits for every byte
+    if hex.len() != SIGNING_KEY_SIZE * 2 {
+        return Err(Error::Configuration(format!(
+            "has to be {} hex digits (currently: {}), `memo_server gen-signing-key` creates one",
+            SIGNING_KEY_SIZE * 2,
+            hex.len()
+        )));
+    }
+
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(Error::Configuration(String::from(
+            "can only contain hex digits",
+        )));
+    }
+
+    let mut key = [0; SIGNING_KEY_SIZE];
+    for (byte, digits) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits)?, 16)?;
+    }
+
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    const KEY: &str = "00112233445566778899aabbccddeeff00112233445566778899AABBCCDDEEFF";
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (String::from(*k), String::from(*v)))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn test_signing_key() {
+        let key = parse_signing_key(KEY).unwrap();
+        assert_eq!(key[0], 0x00);
+        assert_eq!(key[15], 0xff);
+        assert_eq!(key[31], 0xff);
+
+        assert!(parse_signing_key(&KEY[2..]).is_err());
+        assert!(parse_signing_key(&KEY.replace("ff", "fg")).is_err());
+        assert!(parse_signing_key(&KEY.replace("00", "+0")).is_err());
+    }
+
+    #[test]
+    fn test_layers() {
+        let table: Table = toml::from_str("port = 4000\npostgres_host = \"db\"").unwrap();
+        let config = from_layers(
+            table,
+            env(&[
+                ("POSTGRES_HOST", "override"),
+                ("AUTO_MIGRATE", "true"),
+                ("BIND_ADDRESSES", "127.0.0.1, ::1"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 4000);
+        assert_eq!(config.bind_addresses, ["127.0.0.1", "::1"]);
+        assert_eq!(config.postgres_host, "override");
+        assert!(config.auto_migrate);
+        assert_eq!(config.postgres_port, 5432);
+    }
+
+    #[test]
+    fn test_invalid_layers() {
+        assert!(from_layers(Table::new(), env(&[("PORT", "http")])).is_err());
+        assert!(from_layers(Table::new(), env(&[("PORT", "70000")])).is_err());
+        assert!(from_layers(Table::new(), env(&[("REGISTRATION_MODE", "maybe")])).is_err());
+
+        let typo: Table = toml::from_str("prot = 4000").unwrap();
+        assert!(from_layers(typo, env(&[])).is_err());
+    }
+
+    #[test]
+    fn test_validate() {
+        let config = Config {
+            bind_addresses: vec![String::from("0.0.0.0"), String::from("::1")],
+            www_path: PathBuf::from("."),
+            registration_magic_word: String::from("abracadabra"),
+            session_signing_key: String::from(KEY),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok());
+
+        let config = Config {
+            bind_addresses: vec![String::from("localhost")],
+            postgres_pool_size: 0,
+            password_character_classes: 5,
+            metrics_token: String::from("too short"),
+            ..config
+        };
+        match config.validate() {
+            Err(Error::Configuration(problems)) => assert_eq!(problems.lines().count(), 4),
+            _ => panic!("expected every problem to be reported"),
+        }
+    }
+
+    #[test]
+    fn test_listeners() {
+        let config = Config {
+            bind_addresses: vec![String::from("127.0.0.1"), String::from("::")],
+            port: 8080,
+            ..Default::default()
+        };
+        let addresses: Vec<String> = config
+            .socket_addresses()
+            .unwrap()
+            .iter()
+            .map(SocketAddr::to_string)
+            .collect();
+        assert_eq!(addresses, ["127.0.0.1:8080", "[::]:8080"]);
+        assert!(!config.secure_cookies());
+
+        let config = Config {
+            tls_certificate: Some(PathBuf::from("cert.pem")),
+            tls_private_key: Some(PathBuf::from("key.pem")),
+            ..config
+        };
+        assert!(config.secure_cookies());
+
+        let config = Config {
+            cookie_over_https_only: Some(false),
+            ..config
+        };
+        assert!(!config.secure_cookies());
+    }
+
+    #[test]
+    fn test_redacted() {
+        let config = Config {
+            postgres_password: String::from("hunter2"),
+            session_signing_key: String::from(KEY),
+            metrics_token: String::from("scrape-token-scrape-token-scrape-token"),
+            ..Default::default()
+        };
+        let dump = config.redacted().unwrap();
+
+        assert!(!dump.contains("hunter2"));
+        assert!(!dump.contains(KEY));
+        assert!(!dump.contains("scrape-token"));
+        assert!(dump.contains("postgres_host = \"localhost\""));
+    }
+}
@@ -16,10 +16,10 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use super::pg;
-use crate::error::Result;
+use crate::error::{Error, Result};
 use crate::interop::categories as interop;
 use crate::interop::Key;
-use deadpool_postgres::Pool;
+use deadpool_postgres::{Client, Pool, Transaction};
 use serde::{Deserialize, Serialize};
 use tokio_pg_mapper_derive::PostgresMapper;
 
@@ -28,6 +28,20 @@ use tokio_pg_mapper_derive::PostgresMapper;
 struct Category {
     id: Key,
     title: String,
+    version: i32,
+    parent_id: Option<Key>,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "notes")]
+struct AffectedNotes {
+    affected_notes: i64,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "categories")]
+struct CategoryId {
+    id: Key,
 }
 
 impl From<Category> for interop::Category {
@@ -35,42 +49,55 @@ impl From<Category> for interop::Category {
         interop::Category {
             id: category.id,
             title: category.title,
+            version: category.version,
+            parent_id: category.parent_id,
         }
     }
 }
 
-// note: this returns an array of all the user's categories rather
-// than the standard REST thing of the newly created category only
-// (client requires a list of all the categories for the UI)
+// note: this returns all of the user's categories rather than the
+// standard REST thing of the newly created category only (client
+// requires all the categories for the UI), the handler arranges them
+// into the same tree as get_all
 //
 pub(crate) async fn create(
     db_pool: &Pool,
     user_id: Key,
     category: &interop::ProtoCategory,
 ) -> Result<Vec<interop::Category>> {
+    // the parent (if given) has to be one of the user's categories
     pg::one_from::<Category, interop::Category>(
         db_pool,
-        "INSERT INTO categories(user_id, title)
-         VALUES ($1, $2)
+        "INSERT INTO categories(user_id, title, parent_id)
+         SELECT $1, $2, $3
+         WHERE $3::BIGINT is null
+               or exists (SELECT 1 FROM categories p WHERE p.id = $3 AND p.user_id = $1)
          RETURNING $table_fields",
-        &[&user_id, &category.title],
+        &[&user_id, &category.title, &category.parent_id],
     )
     .await?;
 
     all(db_pool, user_id).await
 }
 
+const ALL: &str = "
+    SELECT c.id,
+           c.title,
+           c.version,
+           c.parent_id
+    FROM   categories c
+    WHERE  c.user_id = $1
+    ORDER BY c.title asc";
+
 pub(crate) async fn all(db_pool: &Pool, user_id: Key) -> Result<Vec<interop::Category>> {
-    pg::many_from::<Category, interop::Category>(
-        db_pool,
-        "SELECT c.id,
-                c.title
-         FROM   categories c
-         WHERE  c.user_id = $1
-         ORDER BY c.title asc",
-        &[&user_id],
-    )
-    .await
+    pg::many_from::<Category, interop::Category>(db_pool, ALL, &[&user_id]).await
+}
+
+pub(crate) async fn all_in_tx(
+    tx: &Transaction<'_>,
+    user_id: Key,
+) -> Result<Vec<interop::Category>> {
+    pg::many::<Category, interop::Category>(tx, ALL, &[&user_id]).await
 }
 
 pub(crate) async fn get(
@@ -81,7 +108,9 @@ pub(crate) async fn get(
     pg::one_from::<Category, interop::Category>(
         db_pool,
         "SELECT c.id,
-                c.title
+                c.title,
+                c.version,
+                c.parent_id
          FROM categories c
          WHERE c.user_id = $1 AND c.id = $2",
         &[&user_id, &category_id],
@@ -89,29 +118,209 @@ pub(crate) async fn get(
     .await
 }
 
+// fails with Error::NotFound if expected_version is given and the category
+// has been edited since that version
+//
 pub(crate) async fn edit(
     db_pool: &Pool,
     user_id: Key,
     category: &interop::ProtoCategory,
     category_id: Key,
+    expected_version: Option<i32>,
 ) -> Result<interop::Category> {
     pg::one_from::<Category, interop::Category>(
         db_pool,
         "UPDATE categories
-         SET title = $3
-         WHERE id = $2 and user_id = $1
+         SET title = $3, version = version + 1, updated_at = now()
+         WHERE id = $2 and user_id = $1 and ($4::INTEGER is null or version = $4)
          RETURNING $table_fields",
-        &[&user_id, &category_id, &category.title],
+        &[&user_id, &category_id, &category.title, &expected_version],
     )
     .await
 }
 
-pub(crate) async fn delete(db_pool: &Pool, user_id: Key, id: Key) -> Result<()> {
-    pg::zero_from(
-        db_pool,
+// moving a category underneath itself or one of its own descendants would
+// create a cycle, so those moves fail with Error::CategoryCycle
+//
+// all of the user's categories are locked while checking, otherwise two
+// concurrent moves could each pass the check and still create a cycle
+//
+pub(crate) async fn move_to(
+    db_pool: &Pool,
+    user_id: Key,
+    category_id: Key,
+    parent_id: Option<Key>,
+) -> Result<interop::Category> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    pg::zero(
+        &tx,
+        "SELECT c.id
+         FROM   categories c
+         WHERE  c.user_id = $1
+         ORDER BY c.id
+         FOR UPDATE",
+        &[&user_id],
+    )
+    .await?;
+
+    if let Some(parent_id) = parent_id {
+        // the parent has to be one of the user's categories
+        pg::one::<CategoryId, CategoryId>(
+            &tx,
+            "SELECT c.id
+             FROM categories c
+             WHERE c.user_id = $1 AND c.id = $2",
+            &[&user_id, &parent_id],
+        )
+        .await?;
+
+        // UNION rather than UNION ALL so that the query still terminates
+        // if the existing data already contains a cycle
+        let ancestors = pg::many::<CategoryId, CategoryId>(
+            &tx,
+            "WITH RECURSIVE ancestors AS (
+                 SELECT c.id, c.parent_id
+                 FROM   categories c
+                 WHERE  c.id = $2 AND c.user_id = $1
+                 UNION
+                 SELECT c.id, c.parent_id
+                 FROM   categories c
+                        JOIN ancestors a ON c.id = a.parent_id
+             )
+             SELECT a.id
+             FROM   ancestors a
+             WHERE  a.id = $3",
+            &[&user_id, &parent_id, &category_id],
+        )
+        .await?;
+
+        if !ancestors.is_empty() {
+            return Err(Error::CategoryCycle);
+        }
+    }
+
+    let category = pg::one::<Category, interop::Category>(
+        &tx,
+        "UPDATE categories
+         SET parent_id = $3, version = version + 1, updated_at = now()
+         WHERE id = $2 and user_id = $1
+         RETURNING $table_fields",
+        &[&user_id, &category_id, &parent_id],
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(category)
+}
+
+// notes triaged into the category are either moved into another category
+// or untriaged, if neither option is given and there are notes in the
+// category the deletion fails with Error::CategoryInUse. notes in the bin
+// still belong to the category, so they're counted and moved as well
+//
+// triage rules follow the notes into the other category, otherwise they're
+// deleted along with the category. any child categories are moved up to the
+// deleted category's parent
+//
+pub(crate) async fn delete(
+    db_pool: &Pool,
+    user_id: Key,
+    id: Key,
+    options: &interop::DeleteCategoryQuery,
+) -> Result<()> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    // lock the category so that notes can't be triaged into it meanwhile
+    let category = pg::one::<Category, interop::Category>(
+        &tx,
+        "SELECT c.id,
+                c.title,
+                c.version,
+                c.parent_id
+         FROM categories c
+         WHERE c.user_id = $1 AND c.id = $2
+         FOR UPDATE",
+        &[&user_id, &id],
+    )
+    .await?;
+
+    let affected = pg::one::<AffectedNotes, AffectedNotes>(
+        &tx,
+        "SELECT count(*) AS affected_notes
+         FROM notes n
+         WHERE n.user_id = $1 AND n.category_id = $2",
+        &[&user_id, &id],
+    )
+    .await?;
+
+    if let Some(reassign_to) = options.reassign_to {
+        if reassign_to == id {
+            return Err(Error::InvalidResource);
+        }
+        // the destination has to be one of the user's categories
+        pg::one::<CategoryId, CategoryId>(
+            &tx,
+            "SELECT c.id
+             FROM categories c
+             WHERE c.user_id = $1 AND c.id = $2",
+            &[&user_id, &reassign_to],
+        )
+        .await?;
+
+        pg::zero(
+            &tx,
+            "UPDATE notes
+             SET category_id = $3, version = version + 1, updated_at = now()
+             WHERE user_id = $1 AND category_id = $2",
+            &[&user_id, &id, &reassign_to],
+        )
+        .await?;
+
+        pg::zero(
+            &tx,
+            "UPDATE triage_rules
+             SET category_id = $3
+             WHERE user_id = $1 AND category_id = $2",
+            &[&user_id, &id, &reassign_to],
+        )
+        .await?;
+    } else if affected.affected_notes > 0 {
+        if options.untriage {
+            pg::zero(
+                &tx,
+                "UPDATE notes
+                 SET category_id = null, triaged_at = null, version = version + 1, updated_at = now()
+                 WHERE user_id = $1 AND category_id = $2",
+                &[&user_id, &id],
+            )
+            .await?;
+        } else {
+            return Err(Error::CategoryInUse(affected.affected_notes));
+        }
+    }
+
+    pg::zero(
+        &tx,
+        "UPDATE categories
+         SET parent_id = $3
+         WHERE user_id = $1 AND parent_id = $2",
+        &[&user_id, &id, &category.parent_id],
+    )
+    .await?;
+
+    pg::zero(
+        &tx,
         "DELETE FROM categories
          WHERE user_id = $1 AND id = $2",
         &[&user_id, &id],
     )
-    .await
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
 }
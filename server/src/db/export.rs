@@ -0,0 +1,210 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::pg;
+use super::{categories, notes, revisions, rules, tags, users};
+use crate::error::{Error, Result};
+use crate::export;
+use crate::interop::export::{Export, ImportSummary};
+use crate::interop::Key;
+use deadpool_postgres::{Client, Pool};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use tokio_pg_mapper_derive::PostgresMapper;
+
+#[allow(unused_imports)]
+use tracing::info;
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "notes")]
+struct InsertedId {
+    id: Key,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "tags")]
+struct ImportedTag {
+    id: Key,
+    // false when the tag was merged with an existing one
+    inserted: bool,
+}
+
+// everything the user has created
+//
+// read from a single snapshot so that the parts of the export are consistent
+// with each other, e.g. no note refers to a category created since
+//
+pub(crate) async fn all(db_pool: &Pool, user_id: Key) -> Result<Export> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+    tx.batch_execute("SET TRANSACTION ISOLATION LEVEL REPEATABLE READ, READ ONLY")
+        .await?;
+
+    let mut revisions_by_note = HashMap::new();
+    for (note_id, revision) in revisions::export_all(&tx, user_id).await? {
+        revisions_by_note.entry(note_id).or_default().push(revision);
+    }
+    let mut notes = notes::export_all(&tx, user_id).await?;
+    for note in notes.iter_mut() {
+        note.revisions = revisions_by_note.remove(&note.id).unwrap_or_default();
+    }
+
+    let export = Export {
+        exported_at: chrono::Utc::now(),
+        user: users::export_details(&tx, user_id).await?,
+        categories: categories::all_in_tx(&tx, user_id).await?,
+        tags: tags::all_in_tx(&tx, user_id).await?,
+        rules: rules::all_in_tx(&tx, user_id).await?,
+        notes,
+    };
+
+    tx.commit().await?;
+
+    Ok(export)
+}
+
+// adds the contents of an export to the user's existing data in a single
+// transaction. everything gets a new id, tags are merged with existing tags
+// of the same title and references to anything missing from the export are
+// dropped
+//
+pub(crate) async fn import(db_pool: &Pool, user_id: Key, export: &Export) -> Result<ImportSummary> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    let mut summary = ImportSummary::default();
+
+    let mut category_ids: HashMap<Key, Key> = HashMap::new();
+    for category in export::parents_first(&export.categories)? {
+        let parent_id = category
+            .parent_id
+            .and_then(|parent_id| category_ids.get(&parent_id).copied());
+        let inserted = pg::one::<InsertedId, InsertedId>(
+            &tx,
+            "INSERT INTO categories(user_id, parent_id, title)
+             VALUES ($1, $2, $3)
+             RETURNING id",
+            &[&user_id, &parent_id, &category.title],
+        )
+        .await?;
+        category_ids.insert(category.id, inserted.id);
+        summary.categories += 1;
+    }
+
+    let mut tag_ids: HashMap<Key, Key> = HashMap::new();
+    for tag in &export.tags {
+        // xmax is only 0 for a row that was just inserted
+        let imported = pg::one::<ImportedTag, ImportedTag>(
+            &tx,
+            "INSERT INTO tags(user_id, title)
+             VALUES ($1, $2)
+             ON CONFLICT (user_id, title) DO UPDATE SET title = EXCLUDED.title
+             RETURNING id, (xmax = 0) AS inserted",
+            &[&user_id, &tag.title],
+        )
+        .await?;
+        tag_ids.insert(tag.id, imported.id);
+        if imported.inserted {
+            summary.tags += 1;
+        }
+    }
+
+    for rule in &export.rules {
+        if let Some(category_id) = category_ids.get(&rule.category_id) {
+            pg::zero(
+                &tx,
+                "INSERT INTO triage_rules(user_id, category_id, priority, kind, pattern, enabled)
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &user_id,
+                    category_id,
+                    &rule.priority,
+                    &rule.kind,
+                    &rule.pattern,
+                    &rule.enabled,
+                ],
+            )
+            .await?;
+            summary.rules += 1;
+        }
+    }
+
+    for note in &export.notes {
+        let category_id = note
+            .category_id
+            .and_then(|category_id| category_ids.get(&category_id).copied());
+        // a note can't be triaged without a category
+        let triaged_at = category_id.and(note.triaged_at);
+
+        let inserted = pg::one::<InsertedId, InsertedId>(
+            &tx,
+            "INSERT INTO notes(user_id, category_id, title, content, version, bookmarked,
+                               created_at, updated_at, triaged_at, deleted_at)
+             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)
+             RETURNING id",
+            &[
+                &user_id,
+                &category_id,
+                &note.title,
+                &note.content,
+                &note.version,
+                &note.bookmarked,
+                &note.created_at,
+                &note.updated_at,
+                &triaged_at,
+                &note.deleted_at,
+            ],
+        )
+        .await?;
+        summary.notes += 1;
+
+        for tag_id in note.tags.iter().filter_map(|id| tag_ids.get(id)) {
+            pg::zero(
+                &tx,
+                "INSERT INTO note_tags(note_id, tag_id)
+                 VALUES ($1, $2)
+                 ON CONFLICT DO NOTHING",
+                &[&inserted.id, tag_id],
+            )
+            .await?;
+        }
+
+        for revision in &note.revisions {
+            pg::zero(
+                &tx,
+                "INSERT INTO note_revisions(note_id, user_id, revision, created_at, title, content)
+                 VALUES ($1, $2, $3, $4, $5, $6)",
+                &[
+                    &inserted.id,
+                    &user_id,
+                    &revision.revision,
+                    &revision.created_at,
+                    &revision.title,
+                    &revision.content,
+                ],
+            )
+            .await?;
+            summary.revisions += 1;
+        }
+    }
+
+    tx.commit().await?;
+
+    info!("imported {:?} for user {}", summary, user_id);
+
+    Ok(summary)
+}
@@ -0,0 +1,26 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::pg;
+use crate::error::Result;
+use deadpool_postgres::Pool;
+
+// fails if a client can't be taken from the pool or the database doesn't respond
+//
+pub(crate) async fn ping(db_pool: &Pool) -> Result<()> {
+    pg::zero_from(db_pool, "SELECT 1", &[]).await
+}
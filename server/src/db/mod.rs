@@ -16,6 +16,24 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 pub mod categories;
+pub mod export;
+pub mod health;
 pub mod notes;
 mod pg;
+pub mod revisions;
+pub mod rules;
+pub mod sessions;
+pub mod tags;
+pub mod tokens;
+pub mod totp;
 pub mod users;
+
+// sql expression for the sha256 of the token given as the first parameter,
+// session and api tokens are only ever stored in this form
+//
+const TOKEN_HASH: &str = "encode(sha256(convert_to($1, 'UTF8')), 'hex')";
+
+// sql projection of a note's tag ids, the note has to be aliased as n
+//
+const NOTE_TAGS: &str =
+    "ARRAY(SELECT nt.tag_id FROM note_tags nt WHERE nt.note_id = n.id ORDER BY nt.tag_id) AS tags";
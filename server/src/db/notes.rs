@@ -15,12 +15,16 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use super::pg;
-use crate::error::Result;
+use super::revisions;
+use super::rules;
+use super::{pg, NOTE_TAGS};
+use crate::error::{Error, Result};
 use crate::interop::categories as interop_categories;
+use crate::interop::export as interop_export;
 use crate::interop::notes as interop;
-use crate::interop::Key;
-use deadpool_postgres::Pool;
+use crate::interop::{Key, Page, PageParams};
+use crate::snippet;
+use deadpool_postgres::{Client, Pool, Transaction};
 use serde::{Deserialize, Serialize};
 use tokio_pg_mapper_derive::PostgresMapper;
 
@@ -35,6 +39,8 @@ struct Note {
     content: String,
     triaged_at: Option<chrono::DateTime<chrono::Utc>>,
     category_id: Option<Key>,
+    version: i32,
+    tags: Vec<Key>,
 }
 
 //  pub created_at: chrono::DateTime<chrono::Utc>,
@@ -45,12 +51,117 @@ pub struct NoteId {
     pub id: Key,
 }
 
+// a category or tag that notes are being moved into or tagged with
+//
+#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "categories")]
+struct DestinationId {
+    id: Key,
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "notes")]
+struct SearchResult {
+    id: Key,
+    title: String,
+    triaged_at: Option<chrono::DateTime<chrono::Utc>>,
+    category_id: Option<Key>,
+    deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+    rank: f32,
+    title_snippet: String,
+    content_snippet: String,
+}
+
+impl From<SearchResult> for interop::SearchResult {
+    fn from(r: SearchResult) -> interop::SearchResult {
+        interop::SearchResult {
+            id: r.id,
+            title: r.title,
+            triaged_at: r.triaged_at,
+            category_id: r.category_id,
+            deleted_at: r.deleted_at,
+            rank: r.rank,
+            title_snippet: snippet::parse(&r.title_snippet),
+            content_snippet: snippet::parse(&r.content_snippet),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "notes")]
+struct ExportedNote {
+    id: Key,
+    title: String,
+    content: String,
+    category_id: Option<Key>,
+    tags: Vec<Key>,
+    bookmarked: bool,
+    version: i32,
+    created_at: chrono::DateTime<chrono::Utc>,
+    updated_at: chrono::DateTime<chrono::Utc>,
+    triaged_at: Option<chrono::DateTime<chrono::Utc>>,
+    deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<ExportedNote> for interop_export::ExportedNote {
+    fn from(n: ExportedNote) -> interop_export::ExportedNote {
+        interop_export::ExportedNote {
+            id: n.id,
+            title: n.title,
+            content: n.content,
+            category_id: n.category_id,
+            tags: n.tags,
+            bookmarked: n.bookmarked,
+            version: n.version,
+            created_at: n.created_at,
+            updated_at: n.updated_at,
+            triaged_at: n.triaged_at,
+            deleted_at: n.deleted_at,
+            revisions: vec![],
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "notes")]
+struct NoteCounts {
+    inbox: i64,
+    triaged: i64,
+    binned: i64,
+}
+
+impl From<NoteCounts> for interop::NoteCounts {
+    fn from(c: NoteCounts) -> interop::NoteCounts {
+        interop::NoteCounts {
+            inbox: c.inbox,
+            triaged: c.triaged,
+            binned: c.binned,
+        }
+    }
+}
+
 impl From<Note> for interop::Note {
     fn from(n: Note) -> interop::Note {
         interop::Note {
             id: n.id,
             title: n.title,
             content: n.content,
+            version: n.version,
+            tags: n.tags,
+        }
+    }
+}
+
+impl From<Note> for interop::CreatedNote {
+    fn from(n: Note) -> interop::CreatedNote {
+        interop::CreatedNote {
+            id: n.id,
+            title: n.title,
+            content: n.content,
+            triaged_at: n.triaged_at,
+            category_id: n.category_id,
+            version: n.version,
+            tags: n.tags,
         }
     }
 }
@@ -63,69 +174,265 @@ impl From<Note> for interop::TriagedNote {
             content: n.content,
             triaged_at: n.triaged_at.expect("triaged_at is required"),
             category_id: n.category_id.expect("category_id is required"),
+            version: n.version,
+            tags: n.tags,
         }
     }
 }
 
+// the user's triage rules are applied to the new note, so it may
+// immediately be moved out of the inbox
+//
 pub(crate) async fn create(
     db_pool: &Pool,
     user_id: Key,
     note: &interop::ProtoNote,
-) -> Result<interop::Note> {
-    pg::one_from::<Note, interop::Note>(
-        db_pool,
-        "INSERT INTO notes(user_id, title, content)
-         VALUES ($1, $2, $3)
-         RETURNING $table_fields",
-        &[&user_id, &note.title, &note.content]
+    bookmarked: bool,
+) -> Result<interop::CreatedNote> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    let created = pg::one::<NoteId, NoteId>(
+        &tx,
+        "INSERT INTO notes(user_id, title, content, bookmarked)
+         VALUES ($1, $2, $3, $4)
+         RETURNING id",
+        &[&user_id, &note.title, &note.content, &bookmarked],
     )
-    .await
+    .await?;
+
+    rules::apply_to_note(&tx, user_id, created.id).await?;
+
+    // read the note back so that the caller sees where the rules put it
+    //
+    let note = pg::one::<Note, interop::CreatedNote>(
+        &tx,
+        &format!(
+            "SELECT id, title, content, triaged_at, category_id, version,
+                {}
+         FROM notes n
+         WHERE id = $1",
+            NOTE_TAGS
+        ),
+        &[&created.id],
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(note)
 }
 
-pub(crate) async fn all_non_triaged(db_pool: &Pool, user_id: Key) -> Result<Vec<interop::Note>> {
-    pg::many_from::<Note, interop::Note>(
+pub(crate) async fn all_non_triaged(
+    db_pool: &Pool,
+    user_id: Key,
+    page: &PageParams,
+    tag: Option<Key>,
+) -> Result<Page<interop::Note>> {
+    let limit = page.limit();
+    let notes = pg::many_from::<Note, interop::Note>(
         db_pool,
-        "SELECT n.id,
+        &format!(
+            "SELECT n.id,
                 n.title,
                 n.content,
                 n.triaged_at,
-                n.category_id
+                n.category_id,
+                n.version,
+                {}
          FROM   notes n
          WHERE  n.user_id = $1 and n.triaged_at is null and n.deleted_at is null
-         ORDER BY n.id desc",
-        &[&user_id]
+                and ($2::BIGINT is null or n.id < $2)
+                and ($4::BIGINT is null or
+                     exists (SELECT 1 FROM note_tags nt WHERE nt.note_id = n.id and nt.tag_id = $4))
+         ORDER BY n.id desc
+         LIMIT $3",
+            NOTE_TAGS
+        ),
+        &[&user_id, &page.after, &(limit + 1), &tag],
     )
-    .await
+    .await?;
+
+    Ok(Page::from_overfetch(notes, limit, |n| n.id))
 }
 
-pub(crate) async fn all_binned(db_pool: &Pool, user_id: Key) -> Result<Vec<interop::Note>> {
-    pg::many_from::<Note, interop::Note>(
+// binned notes are ordered by deleted_at so the cursor's position is
+// found by looking up when the cursor note was binned
+//
+pub(crate) async fn all_binned(
+    db_pool: &Pool,
+    user_id: Key,
+    page: &PageParams,
+    tag: Option<Key>,
+) -> Result<Page<interop::Note>> {
+    let limit = page.limit();
+    let notes = pg::many_from::<Note, interop::Note>(
         db_pool,
-        "SELECT n.id,
+        &format!(
+            "SELECT n.id,
                 n.title,
                 n.content,
                 n.triaged_at,
-                n.category_id
+                n.category_id,
+                n.version,
+                {}
          FROM   notes n
          WHERE  n.user_id = $1 and n.deleted_at is not null
-         ORDER BY n.deleted_at desc",
-        &[&user_id],
+                and ($2::BIGINT is null or
+                     (n.deleted_at, n.id) < (SELECT c.deleted_at, c.id
+                                             FROM   notes c
+                                             WHERE  c.id = $2 and c.user_id = $1))
+                and ($4::BIGINT is null or
+                     exists (SELECT 1 FROM note_tags nt WHERE nt.note_id = n.id and nt.tag_id = $4))
+         ORDER BY n.deleted_at desc, n.id desc
+         LIMIT $3",
+            NOTE_TAGS
+        ),
+        &[&user_id, &page.after, &(limit + 1), &tag],
     )
-    .await
+    .await?;
+
+    Ok(Page::from_overfetch(notes, limit, |n| n.id))
 }
 
-pub(crate) async fn triaged_all(db_pool: &Pool, user_id: Key) -> Result<Vec<interop::TriagedNote>> {
-    pg::many_from::<Note, interop::TriagedNote>(
+pub(crate) async fn triaged_all(
+    db_pool: &Pool,
+    user_id: Key,
+    page: &PageParams,
+    tag: Option<Key>,
+) -> Result<Page<interop::TriagedNote>> {
+    let limit = page.limit();
+    let notes = pg::many_from::<Note, interop::TriagedNote>(
         db_pool,
-        "SELECT n.id,
+        &format!(
+            "SELECT n.id,
                 n.title,
                 n.content,
                 n.triaged_at,
-                n.category_id
+                n.category_id,
+                n.version,
+                {}
          FROM   notes n
          WHERE  n.user_id = $1 and n.triaged_at is not null and n.deleted_at is null
-         ORDER BY n.id desc",
-        &[&user_id],
+                and ($2::BIGINT is null or n.id < $2)
+                and ($4::BIGINT is null or
+                     exists (SELECT 1 FROM note_tags nt WHERE nt.note_id = n.id and nt.tag_id = $4))
+         ORDER BY n.id desc
+         LIMIT $3",
+            NOTE_TAGS
+        ),
+        &[&user_id, &page.after, &(limit + 1), &tag],
+    )
+    .await?;
+
+    Ok(Page::from_overfetch(notes, limit, |n| n.id))
+}
+
+// include_descendants also returns the notes of every category nested
+// (at any depth) underneath the given category
+//
+pub(crate) async fn triaged_in_category(
+    db_pool: &Pool,
+    user_id: Key,
+    category_id: Key,
+    include_descendants: bool,
+    page: &PageParams,
+    tag: Option<Key>,
+) -> Result<Page<interop::TriagedNote>> {
+    let limit = page.limit();
+    let notes = pg::many_from::<Note, interop::TriagedNote>(
+        db_pool,
+        &format!(
+            "WITH RECURSIVE tree AS (
+             SELECT c.id
+             FROM   categories c
+             WHERE  c.id = $2 AND c.user_id = $1
+             UNION
+             SELECT c.id
+             FROM   categories c
+                    JOIN tree t ON c.parent_id = t.id
+             WHERE  $3::BOOLEAN
+         )
+         SELECT n.id,
+                n.title,
+                n.content,
+                n.triaged_at,
+                n.category_id,
+                n.version,
+                {}
+         FROM   notes n
+         WHERE  n.user_id = $1 and n.triaged_at is not null and n.deleted_at is null
+                and n.category_id IN (SELECT t.id FROM tree t)
+                and ($4::BIGINT is null or n.id < $4)
+                and ($6::BIGINT is null or
+                     exists (SELECT 1 FROM note_tags nt WHERE nt.note_id = n.id and nt.tag_id = $6))
+         ORDER BY n.id desc
+         LIMIT $5",
+            NOTE_TAGS
+        ),
+        &[
+            &user_id,
+            &category_id,
+            &include_descendants,
+            &page.after,
+            &(limit + 1),
+            &tag,
+        ],
+    )
+    .await?;
+
+    Ok(Page::from_overfetch(notes, limit, |n| n.id))
+}
+
+const SEARCH_LIMIT: i64 = 100;
+
+fn state_filter(state: Option<interop::NoteState>) -> &'static str {
+    match state {
+        Some(interop::NoteState::Inbox) => "n.triaged_at is null and n.deleted_at is null",
+        Some(interop::NoteState::Triaged) => "n.triaged_at is not null and n.deleted_at is null",
+        Some(interop::NoteState::Bin) => "n.deleted_at is not null",
+        None => "n.deleted_at is null",
+    }
+}
+
+// the tsvector expression has to match the one used by the notes_search_idx
+// index in the search_index migration, otherwise postgres will fall back to a sequential scan
+//
+// chr(2) and chr(3) are snippet::START and snippet::STOP
+//
+pub(crate) async fn search(
+    db_pool: &Pool,
+    user_id: Key,
+    query: &interop::SearchQuery,
+) -> Result<Vec<interop::SearchResult>> {
+    let stmt = format!(
+        "SELECT n.id,
+                n.title,
+                n.triaged_at,
+                n.category_id,
+                n.deleted_at,
+                ts_rank(setweight(to_tsvector('english', n.title), 'A') ||
+                        setweight(to_tsvector('english', n.content), 'B'),
+                        q) AS rank,
+                ts_headline('english', translate(n.title, chr(2) || chr(3), ''), q,
+                            'StartSel=' || chr(2) || ', StopSel=' || chr(3) || ', HighlightAll=true') AS title_snippet,
+                ts_headline('english', translate(n.content, chr(2) || chr(3), ''), q,
+                            'StartSel=' || chr(2) || ', StopSel=' || chr(3) || ', MaxFragments=3, MaxWords=20, MinWords=5') AS content_snippet
+         FROM   notes n, plainto_tsquery('english', $2) q
+         WHERE  n.user_id = $1 and {}
+                and (setweight(to_tsvector('english', n.title), 'A') ||
+                     setweight(to_tsvector('english', n.content), 'B')) @@ q
+                and ($4::BIGINT is null or
+                     exists (SELECT 1 FROM note_tags nt WHERE nt.note_id = n.id and nt.tag_id = $4))
+         ORDER BY rank desc, n.id desc
+         LIMIT $3",
+        state_filter(query.state)
+    );
+
+    pg::many_from::<SearchResult, interop::SearchResult>(
+        db_pool,
+        &stmt,
+        &[&user_id, &query.q, &SEARCH_LIMIT, &query.tag],
     )
     .await
 }
@@ -135,13 +442,18 @@ pub(crate) async fn triaged_all(db_pool: &Pool, user_id: Key) -> Result<Vec<inte
 pub(crate) async fn get(db_pool: &Pool, user_id: Key, note_id: Key) -> Result<interop::Note> {
     pg::one_from::<Note, interop::Note>(
         db_pool,
-        "SELECT n.id,
+        &format!(
+            "SELECT n.id,
                 n.title,
                 n.content,
                 n.triaged_at,
-                n.category_id
+                n.category_id,
+                n.version,
+                {}
          FROM notes n
          WHERE n.id = $2 AND n.user_id = $1",
+            NOTE_TAGS
+        ),
         &[&user_id, &note_id],
     )
     .await
@@ -154,13 +466,18 @@ pub(crate) async fn triaged_get(
 ) -> Result<interop::TriagedNote> {
     pg::one_from::<Note, interop::TriagedNote>(
         db_pool,
-        "SELECT n.id,
+        &format!(
+            "SELECT n.id,
                 n.title,
                 n.content,
                 n.triaged_at,
-                n.category_id
+                n.category_id,
+                n.version,
+                {}
         FROM notes n
         WHERE n.id = $2 AND n.user_id = $1",
+            NOTE_TAGS
+        ),
         &[&user_id, &note_id],
     )
     .await
@@ -174,10 +491,14 @@ pub(crate) async fn triage(
 ) -> Result<interop::TriagedNote> {
     pg::one_from::<Note, interop::TriagedNote>(
         db_pool,
-        "UPDATE notes
-         SET triaged_at = now(), category_id = $3
+        &format!(
+            "UPDATE notes n
+         SET triaged_at = now(), category_id = $3, version = version + 1, updated_at = now()
          WHERE id = $2 and user_id = $1
-         RETURNING $table_fields",
+         RETURNING id, title, content, triaged_at, category_id, version,
+                   {}",
+            NOTE_TAGS
+        ),
         &[&user_id, &note_id, &category.id],
     )
     .await
@@ -186,10 +507,14 @@ pub(crate) async fn triage(
 pub(crate) async fn untriage(db_pool: &Pool, user_id: Key, note_id: Key) -> Result<interop::Note> {
     pg::one_from::<Note, interop::Note>(
         db_pool,
-        "UPDATE notes
-         SET triaged_at = null, category_id = null
+        &format!(
+            "UPDATE notes n
+         SET triaged_at = null, category_id = null, version = version + 1, updated_at = now()
          WHERE id = $2 and user_id = $1
-         RETURNING $table_fields",
+         RETURNING id, title, content, triaged_at, category_id, version,
+                   {}",
+            NOTE_TAGS
+        ),
         &[&user_id, &note_id],
     )
     .await
@@ -198,39 +523,107 @@ pub(crate) async fn untriage(db_pool: &Pool, user_id: Key, note_id: Key) -> Resu
 pub(crate) async fn bin(db_pool: &Pool, user_id: Key, note_id: Key) -> Result<interop::Note> {
     pg::one_from::<Note, interop::Note>(
         db_pool,
-        "UPDATE notes
-         SET deleted_at = now()
+        &format!(
+            "UPDATE notes n
+         SET deleted_at = now(), version = version + 1, updated_at = now()
          WHERE id = $2 and user_id = $1
-         RETURNING $table_fields",
+         RETURNING id, title, content, triaged_at, category_id, version,
+                   {}",
+            NOTE_TAGS
+        ),
         &[&user_id, &note_id],
     )
     .await
 }
 
+// the previous title and content are kept in note_revisions
+//
+// fails with Error::NotFound if expected_version is given and the note
+// has been edited since that version
+//
 pub(crate) async fn edit(
     db_pool: &Pool,
     user_id: Key,
     note: &interop::ProtoNote,
     note_id: Key,
+    expected_version: Option<i32>,
 ) -> Result<interop::Note> {
-    pg::one_from::<Note, interop::Note>(
-        db_pool,
-        "UPDATE notes
-         SET title = $3, content = $4
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    revisions::record(&tx, user_id, note_id, &note.title, &note.content).await?;
+
+    let note = pg::one::<Note, interop::Note>(
+        &tx,
+        &format!(
+            "UPDATE notes n
+         SET title = $3, content = $4, version = version + 1, updated_at = now()
+         WHERE id = $2 and user_id = $1 and ($5::INTEGER is null or version = $5)
+         RETURNING id, title, content, triaged_at, category_id, version,
+                   {}",
+            NOTE_TAGS
+        ),
+        &[
+            &user_id,
+            &note_id,
+            &note.title,
+            &note.content,
+            &expected_version,
+        ],
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(note)
+}
+
+// restoring is itself an edit, so the current title and content
+// are recorded as a new revision before being replaced
+//
+pub(crate) async fn restore_revision(
+    db_pool: &Pool,
+    user_id: Key,
+    note_id: Key,
+    revision: i32,
+) -> Result<interop::Note> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    let old = revisions::get_in_tx(&tx, user_id, note_id, revision).await?;
+
+    revisions::record(&tx, user_id, note_id, &old.title, &old.content).await?;
+
+    let note = pg::one::<Note, interop::Note>(
+        &tx,
+        &format!(
+            "UPDATE notes n
+         SET title = $3, content = $4, version = version + 1, updated_at = now()
          WHERE id = $2 and user_id = $1
-         RETURNING $table_fields",
-        &[&user_id, &note_id, &note.title, &note.content],
+         RETURNING id, title, content, triaged_at, category_id, version,
+                   {}",
+            NOTE_TAGS
+        ),
+        &[&user_id, &note_id, &old.title, &old.content],
     )
-    .await
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(note)
 }
 
 pub(crate) async fn unbin(db_pool: &Pool, user_id: Key, note_id: Key) -> Result<interop::Note> {
     pg::one_from::<Note, interop::Note>(
         db_pool,
-        "UPDATE notes
-         SET deleted_at = null
+        &format!(
+            "UPDATE notes n
+         SET deleted_at = null, version = version + 1, updated_at = now()
          WHERE id = $2 and user_id = $1
-         RETURNING $table_fields",
+         RETURNING id, title, content, triaged_at, category_id, version,
+                   {}",
+            NOTE_TAGS
+        ),
         &[&user_id, &note_id],
     )
     .await
@@ -255,3 +648,215 @@ pub(crate) async fn delete_all(db_pool: &Pool, user_id: Key) -> Result<()> {
     )
     .await
 }
+
+// returns the number of notes that were deleted
+//
+pub(crate) async fn purge_expired_bin(
+    db_pool: &Pool,
+    default_retention_days: i32,
+) -> Result<usize> {
+    let purged = pg::many_from::<NoteId, NoteId>(
+        db_pool,
+        "DELETE FROM notes n
+         USING users u
+         WHERE n.user_id = u.id AND n.deleted_at is not null
+               AND n.deleted_at < now() - make_interval(days => COALESCE(u.bin_retention_days, $1))
+         RETURNING n.id",
+        &[&default_retention_days],
+    )
+    .await?;
+
+    Ok(purged.len())
+}
+
+// the action is applied to every note in a single transaction, ids that
+// don't belong to the user are reported as not found and otherwise ignored.
+// notes that the action didn't change (e.g. binning a note that's already in
+// the bin) are reported as unchanged
+//
+// fails with Error::NotFound if the category or tag to triage into or tag
+// with doesn't belong to the user
+//
+pub(crate) async fn bulk(
+    db_pool: &Pool,
+    user_id: Key,
+    request: &interop::BulkRequest,
+) -> Result<Vec<interop::BulkResult>> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    // the destination is locked so that it can't be deleted before the notes
+    // refer to it
+    match request.action {
+        interop::BulkAction::Triage { category_id } => {
+            pg::one::<DestinationId, DestinationId>(
+                &tx,
+                "SELECT c.id
+                 FROM categories c
+                 WHERE c.user_id = $1 AND c.id = $2
+                 FOR SHARE",
+                &[&user_id, &category_id],
+            )
+            .await?;
+        }
+        interop::BulkAction::AddTag { tag_id } => {
+            pg::one::<DestinationId, DestinationId>(
+                &tx,
+                "SELECT t.id
+                 FROM tags t
+                 WHERE t.user_id = $1 AND t.id = $2
+                 FOR SHARE",
+                &[&user_id, &tag_id],
+            )
+            .await?;
+        }
+        _ => {}
+    }
+
+    let found = note_ids(
+        pg::many::<NoteId, NoteId>(
+            &tx,
+            "SELECT n.id
+             FROM notes n
+             WHERE n.user_id = $1 AND n.id = ANY($2)
+             FOR UPDATE",
+            &[&user_id, &request.ids],
+        )
+        .await?,
+    );
+
+    let affected = note_ids(match request.action {
+        interop::BulkAction::Triage { category_id } => {
+            pg::many::<NoteId, NoteId>(
+                &tx,
+                "UPDATE notes
+                 SET triaged_at = now(), category_id = $3, version = version + 1, updated_at = now()
+                 WHERE user_id = $1 AND id = ANY($2)
+                 RETURNING id",
+                &[&user_id, &found, &category_id],
+            )
+            .await?
+        }
+        interop::BulkAction::Bin => {
+            pg::many::<NoteId, NoteId>(
+                &tx,
+                "UPDATE notes
+                 SET deleted_at = now(), version = version + 1, updated_at = now()
+                 WHERE user_id = $1 AND id = ANY($2) AND deleted_at is null
+                 RETURNING id",
+                &[&user_id, &found],
+            )
+            .await?
+        }
+        interop::BulkAction::Unbin => {
+            pg::many::<NoteId, NoteId>(
+                &tx,
+                "UPDATE notes
+                 SET deleted_at = null, version = version + 1, updated_at = now()
+                 WHERE user_id = $1 AND id = ANY($2) AND deleted_at is not null
+                 RETURNING id",
+                &[&user_id, &found],
+            )
+            .await?
+        }
+        interop::BulkAction::Untriage => {
+            pg::many::<NoteId, NoteId>(
+                &tx,
+                "UPDATE notes
+                 SET triaged_at = null, category_id = null, version = version + 1, updated_at = now()
+                 WHERE user_id = $1 AND id = ANY($2) AND triaged_at is not null
+                 RETURNING id",
+                &[&user_id, &found],
+            )
+            .await?
+        }
+        interop::BulkAction::Delete => {
+            pg::many::<NoteId, NoteId>(
+                &tx,
+                "DELETE FROM notes
+                 WHERE user_id = $1 AND id = ANY($2)
+                 RETURNING id",
+                &[&user_id, &found],
+            )
+            .await?
+        }
+        interop::BulkAction::AddTag { tag_id } => {
+            pg::many::<NoteId, NoteId>(
+                &tx,
+                "INSERT INTO note_tags(note_id, tag_id)
+                 SELECT unnest($1::BIGINT[]), $2
+                 ON CONFLICT DO NOTHING
+                 RETURNING note_id AS id",
+                &[&found, &tag_id],
+            )
+            .await?
+        }
+    });
+
+    tx.commit().await?;
+
+    let results = request
+        .ids
+        .iter()
+        .map(|id| interop::BulkResult {
+            id: *id,
+            status: if affected.contains(id) {
+                interop::BulkStatus::Done
+            } else if found.contains(id) {
+                interop::BulkStatus::Unchanged
+            } else {
+                interop::BulkStatus::NotFound
+            },
+        })
+        .collect();
+
+    Ok(results)
+}
+
+fn note_ids(notes: Vec<NoteId>) -> Vec<Key> {
+    notes.into_iter().map(|n| n.id).collect()
+}
+
+// every one of the user's notes, without their revisions
+//
+pub(crate) async fn export_all(
+    tx: &Transaction<'_>,
+    user_id: Key,
+) -> Result<Vec<interop_export::ExportedNote>> {
+    pg::many::<ExportedNote, interop_export::ExportedNote>(
+        tx,
+        &format!(
+            "SELECT n.id,
+                n.title,
+                n.content,
+                n.category_id,
+                {},
+                n.bookmarked,
+                n.version,
+                n.created_at,
+                n.updated_at,
+                n.triaged_at,
+                n.deleted_at
+         FROM   notes n
+         WHERE  n.user_id = $1
+         ORDER BY n.id",
+            NOTE_TAGS
+        ),
+        &[&user_id],
+    )
+    .await
+}
+
+// across every user, for the metrics endpoint
+//
+pub(crate) async fn counts(db_pool: &Pool) -> Result<interop::NoteCounts> {
+    pg::one_from::<NoteCounts, interop::NoteCounts>(
+        db_pool,
+        "SELECT count(*) FILTER (WHERE n.triaged_at is null AND n.deleted_at is null) AS inbox,
+                count(*) FILTER (WHERE n.triaged_at is not null AND n.deleted_at is null) AS triaged,
+                count(*) FILTER (WHERE n.deleted_at is not null) AS binned
+         FROM   notes n",
+        &[],
+    )
+    .await
+}
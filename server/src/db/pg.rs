@@ -45,6 +45,79 @@ pub async fn zero(
     }
 }
 
+// transactional version of one_from
+//
+pub async fn one<S, T>(
+    tx: &Transaction<'_>,
+    sql_query: &str,
+    sql_params: &[&(dyn tokio_postgres::types::ToSql + std::marker::Sync)],
+) -> Result<T>
+where
+    S: FromTokioPostgresRow,
+    T: From<S>,
+{
+    let _stmt = sql_query;
+    let _stmt = _stmt.replace("$table_fields", &S::sql_table_fields());
+    let stmt = match tx.prepare(&_stmt).await {
+        Ok(stmt) => stmt,
+        Err(e) => {
+            error!("{}", e);
+            error!("QUERY: {}", &sql_query);
+            return Err(Error::from(e));
+        }
+    };
+
+    let res = tx
+        .query(&stmt, sql_params)
+        .await?
+        .iter()
+        .map(|row| T::from(S::from_row_ref(row).unwrap()))
+        .collect::<Vec<T>>()
+        .pop()
+        .ok_or(Error::NotFound);
+
+    match res {
+        Ok(_) => res,
+        Err(e) => {
+            error!("{}", e);
+            error!("QUERY: {}", &sql_query);
+            Err(e)
+        }
+    }
+}
+
+// transactional version of many_from
+//
+pub async fn many<S, T>(
+    tx: &Transaction<'_>,
+    sql_query: &str,
+    sql_params: &[&(dyn tokio_postgres::types::ToSql + std::marker::Sync)],
+) -> Result<Vec<T>>
+where
+    S: FromTokioPostgresRow,
+    T: From<S>,
+{
+    let _stmt = sql_query;
+    let _stmt = _stmt.replace("$table_fields", &S::sql_table_fields());
+    let stmt = match tx.prepare(&_stmt).await {
+        Ok(stmt) => stmt,
+        Err(e) => {
+            error!("{}", e);
+            error!("QUERY: {}", &sql_query);
+            return Err(Error::from(e));
+        }
+    };
+
+    let vec = tx
+        .query(&stmt, sql_params)
+        .await?
+        .iter()
+        .map(|row| T::from(S::from_row_ref(row).unwrap()))
+        .collect::<Vec<T>>();
+
+    Ok(vec)
+}
+
 pub async fn one_non_transactional<T>(
     db_pool: &Pool,
     sql_query: &str,
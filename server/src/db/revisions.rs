@@ -0,0 +1,192 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use super::pg;
+use crate::error::Result;
+use crate::interop::revisions as interop;
+use crate::interop::Key;
+use deadpool_postgres::{Pool, Transaction};
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper_derive::PostgresMapper;
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "note_revisions")]
+struct Revision {
+    revision: i32,
+    created_at: chrono::DateTime<chrono::Utc>,
+    title: String,
+    content: String,
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "note_revisions")]
+struct NoteRevision {
+    note_id: Key,
+    revision: i32,
+    created_at: chrono::DateTime<chrono::Utc>,
+    title: String,
+    content: String,
+}
+
+impl From<NoteRevision> for (Key, interop::Revision) {
+    fn from(r: NoteRevision) -> (Key, interop::Revision) {
+        (
+            r.note_id,
+            interop::Revision {
+                revision: r.revision,
+                created_at: r.created_at,
+                title: r.title,
+                content: r.content,
+            },
+        )
+    }
+}
+
+impl From<Revision> for interop::Revision {
+    fn from(r: Revision) -> interop::Revision {
+        interop::Revision {
+            revision: r.revision,
+            created_at: r.created_at,
+            title: r.title,
+            content: r.content,
+        }
+    }
+}
+
+impl From<Revision> for interop::RevisionSummary {
+    fn from(r: Revision) -> interop::RevisionSummary {
+        interop::RevisionSummary {
+            revision: r.revision,
+            created_at: r.created_at,
+            title: r.title,
+        }
+    }
+}
+
+// saves the note's current title and content as a new revision, unless
+// they're identical to the title and content that are about to replace them
+//
+// the note row is locked until the transaction ends so that concurrent
+// edits can't both claim the same revision number
+//
+pub(crate) async fn record(
+    tx: &Transaction<'_>,
+    user_id: Key,
+    note_id: Key,
+    title: &str,
+    content: &str,
+) -> Result<()> {
+    pg::zero(
+        tx,
+        "SELECT n.id
+         FROM notes n
+         WHERE n.id = $2 AND n.user_id = $1
+         FOR UPDATE",
+        &[&user_id, &note_id],
+    )
+    .await?;
+
+    pg::zero(
+        tx,
+        "INSERT INTO note_revisions(note_id, user_id, revision, title, content)
+         SELECT n.id,
+                n.user_id,
+                COALESCE((SELECT max(r.revision)
+                          FROM note_revisions r
+                          WHERE r.note_id = n.id), 0) + 1,
+                n.title,
+                n.content
+         FROM notes n
+         WHERE n.id = $2 AND n.user_id = $1
+               AND (n.title <> $3 OR n.content <> $4)",
+        &[&user_id, &note_id, &title, &content],
+    )
+    .await
+}
+
+pub(crate) async fn all(
+    db_pool: &Pool,
+    user_id: Key,
+    note_id: Key,
+) -> Result<Vec<interop::RevisionSummary>> {
+    pg::many_from::<Revision, interop::RevisionSummary>(
+        db_pool,
+        "SELECT r.revision,
+                r.created_at,
+                r.title,
+                r.content
+         FROM   note_revisions r
+         WHERE  r.note_id = $2 AND r.user_id = $1
+         ORDER BY r.revision desc",
+        &[&user_id, &note_id],
+    )
+    .await
+}
+
+pub(crate) async fn get(
+    db_pool: &Pool,
+    user_id: Key,
+    note_id: Key,
+    revision: i32,
+) -> Result<interop::Revision> {
+    pg::one_from::<Revision, interop::Revision>(
+        db_pool,
+        "SELECT r.revision,
+                r.created_at,
+                r.title,
+                r.content
+         FROM   note_revisions r
+         WHERE  r.note_id = $2 AND r.user_id = $1 AND r.revision = $3",
+        &[&user_id, &note_id, &revision],
+    )
+    .await
+}
+
+pub(crate) async fn get_in_tx(
+    tx: &Transaction<'_>,
+    user_id: Key,
+    note_id: Key,
+    revision: i32,
+) -> Result<interop::Revision> {
+    pg::one::<Revision, interop::Revision>(
+        tx,
+        "SELECT r.revision,
+                r.created_at,
+                r.title,
+                r.content
+         FROM   note_revisions r
+         WHERE  r.note_id = $2 AND r.user_id = $1 AND r.revision = $3",
+        &[&user_id, &note_id, &revision],
+    )
+    .await
+}
+
+// every revision of every one of the user's notes, paired with the note's id
+//
+pub(crate) async fn export_all(
+    tx: &Transaction<'_>,
+    user_id: Key,
+) -> Result<Vec<(Key, interop::Revision)>> {
+    pg::many::<NoteRevision, (Key, interop::Revision)>(
+        tx,
+        "SELECT note_id, revision, created_at, title, content
+         FROM note_revisions
+         WHERE user_id = $1
+         ORDER BY note_id, revision",
+        &[&user_id],
+    )
+    .await
+}
@@ -0,0 +1,245 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::pg;
+use crate::error::{Error, Result};
+use crate::interop::errors::FieldError;
+use crate::interop::rules as interop;
+use crate::interop::Key;
+use deadpool_postgres::{Pool, Transaction};
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper_derive::PostgresMapper;
+use tokio_postgres::error::SqlState;
+
+#[allow(unused_imports)]
+use tracing::info;
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "triage_rules")]
+struct Rule {
+    id: Key,
+    priority: i32,
+    kind: interop::RuleKind,
+    pattern: String,
+    category_id: Key,
+    enabled: bool,
+}
+
+impl From<Rule> for interop::Rule {
+    fn from(r: Rule) -> interop::Rule {
+        interop::Rule {
+            id: r.id,
+            priority: r.priority,
+            kind: r.kind,
+            pattern: r.pattern,
+            category_id: r.category_id,
+            enabled: r.enabled,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "notes")]
+struct RuleMatch {
+    note_id: Key,
+    note_title: String,
+    rule_id: Key,
+    category_id: Key,
+}
+
+impl From<RuleMatch> for interop::RuleMatch {
+    fn from(m: RuleMatch) -> interop::RuleMatch {
+        interop::RuleMatch {
+            note_id: m.note_id,
+            note_title: m.note_title,
+            rule_id: m.rule_id,
+            category_id: m.category_id,
+        }
+    }
+}
+
+// the first enabled rule (ordered by priority) that matches an inbox note
+// decides the note's category. $1 is the user and $2 restricts the matching
+// to a single note when it isn't null
+//
+const MATCHES: &str = "
+    SELECT DISTINCT ON (n.id)
+           n.id AS note_id,
+           n.title AS note_title,
+           r.id AS rule_id,
+           r.category_id
+    FROM   notes n
+           JOIN triage_rules r ON r.user_id = n.user_id AND r.enabled
+    WHERE  n.user_id = $1 AND n.triaged_at is null AND n.deleted_at is null
+           AND ($2::BIGINT is null OR n.id = $2)
+           AND CASE r.kind
+                 WHEN 'title_regex' THEN n.title ~* r.pattern
+                 WHEN 'url_domain' THEN EXISTS (
+                     SELECT 1
+                     FROM   regexp_matches(n.content, 'https?://([^/\\s:?#]+)', 'gi') AS m(host)
+                     WHERE  lower(m.host[1]) = lower(r.pattern)
+                            OR right(lower(m.host[1]), length(r.pattern) + 1) = '.' || lower(r.pattern))
+                 WHEN 'bookmark' THEN n.bookmarked
+               END
+    ORDER BY n.id, r.priority, r.id";
+
+pub(crate) async fn create(
+    db_pool: &Pool,
+    user_id: Key,
+    rule: &interop::ProtoRule,
+) -> Result<interop::Rule> {
+    validate_pattern(db_pool, rule).await?;
+
+    // the category has to be one of the user's categories
+    pg::one_from::<Rule, interop::Rule>(
+        db_pool,
+        "INSERT INTO triage_rules(user_id, priority, kind, pattern, category_id, enabled)
+         SELECT $1, $2, $3, $4, $5, $6
+         WHERE exists (SELECT 1 FROM categories c WHERE c.id = $5 AND c.user_id = $1)
+         RETURNING $table_fields",
+        &[
+            &user_id,
+            &rule.priority,
+            &rule.kind,
+            &rule.pattern,
+            &rule.category_id,
+            &rule.enabled.unwrap_or(true),
+        ],
+    )
+    .await
+}
+
+const ALL: &str = "
+    SELECT $table_fields
+    FROM   triage_rules
+    WHERE  user_id = $1
+    ORDER BY priority, id";
+
+pub(crate) async fn all(db_pool: &Pool, user_id: Key) -> Result<Vec<interop::Rule>> {
+    pg::many_from::<Rule, interop::Rule>(db_pool, ALL, &[&user_id]).await
+}
+
+pub(crate) async fn all_in_tx(tx: &Transaction<'_>, user_id: Key) -> Result<Vec<interop::Rule>> {
+    pg::many::<Rule, interop::Rule>(tx, ALL, &[&user_id]).await
+}
+
+pub(crate) async fn get(db_pool: &Pool, user_id: Key, rule_id: Key) -> Result<interop::Rule> {
+    pg::one_from::<Rule, interop::Rule>(
+        db_pool,
+        "SELECT $table_fields
+         FROM   triage_rules
+         WHERE  user_id = $1 AND id = $2",
+        &[&user_id, &rule_id],
+    )
+    .await
+}
+
+pub(crate) async fn edit(
+    db_pool: &Pool,
+    user_id: Key,
+    rule: &interop::ProtoRule,
+    rule_id: Key,
+) -> Result<interop::Rule> {
+    validate_pattern(db_pool, rule).await?;
+
+    pg::one_from::<Rule, interop::Rule>(
+        db_pool,
+        "UPDATE triage_rules
+         SET priority = $3, kind = $4, pattern = $5, category_id = $6, enabled = $7
+         WHERE user_id = $1 AND id = $2
+               AND exists (SELECT 1 FROM categories c WHERE c.id = $6 AND c.user_id = $1)
+         RETURNING $table_fields",
+        &[
+            &user_id,
+            &rule_id,
+            &rule.priority,
+            &rule.kind,
+            &rule.pattern,
+            &rule.category_id,
+            &rule.enabled.unwrap_or(true),
+        ],
+    )
+    .await
+}
+
+pub(crate) async fn delete(db_pool: &Pool, user_id: Key, rule_id: Key) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        "DELETE FROM triage_rules
+         WHERE user_id = $1 AND id = $2",
+        &[&user_id, &rule_id],
+    )
+    .await
+}
+
+// the notes in the user's inbox that would be triaged by their rules
+//
+pub(crate) async fn matches(db_pool: &Pool, user_id: Key) -> Result<Vec<interop::RuleMatch>> {
+    let no_note: Option<Key> = None;
+
+    pg::many_from::<RuleMatch, interop::RuleMatch>(db_pool, MATCHES, &[&user_id, &no_note]).await
+}
+
+pub(crate) async fn apply(db_pool: &Pool, user_id: Key) -> Result<Vec<interop::RuleMatch>> {
+    let no_note: Option<Key> = None;
+    let stmt = format!(
+        "WITH matches AS ({})
+         UPDATE notes n
+         SET triaged_at = now(), category_id = m.category_id, version = version + 1, updated_at = now()
+         FROM matches m
+         WHERE n.id = m.note_id
+         RETURNING m.note_id, m.note_title, m.rule_id, m.category_id",
+        MATCHES
+    );
+
+    pg::many_from::<RuleMatch, interop::RuleMatch>(db_pool, &stmt, &[&user_id, &no_note]).await
+}
+
+// used when a note is created
+//
+pub(crate) async fn apply_to_note(tx: &Transaction<'_>, user_id: Key, note_id: Key) -> Result<()> {
+    let stmt = format!(
+        "WITH matches AS ({})
+         UPDATE notes n
+         SET triaged_at = now(), category_id = m.category_id, version = version + 1, updated_at = now()
+         FROM matches m
+         WHERE n.id = m.note_id",
+        MATCHES
+    );
+
+    pg::zero(tx, &stmt, &[&user_id, &Some(note_id)]).await
+}
+
+// an invalid regular expression would otherwise only be noticed when the
+// rules are next applied, which could be in the middle of creating a note
+//
+async fn validate_pattern(db_pool: &Pool, rule: &interop::ProtoRule) -> Result<()> {
+    if let interop::RuleKind::TitleRegex = rule.kind {
+        let client = db_pool.get().await.map_err(Error::DeadPool)?;
+        if let Err(e) = client.query("SELECT '' ~* $1", &[&rule.pattern]).await {
+            if e.code() == Some(&SqlState::INVALID_REGULAR_EXPRESSION) {
+                return Err(Error::Validation(vec![FieldError {
+                    field: "pattern",
+                    message: String::from("isn't a valid regular expression"),
+                }]));
+            }
+            return Err(Error::from(e));
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,162 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{pg, TOKEN_HASH};
+use crate::error::Result;
+use crate::interop::sessions as interop;
+use crate::interop::Key;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper_derive::PostgresMapper;
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "sessions")]
+struct Session {
+    id: Key,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    last_seen_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<Session> for interop::Session {
+    fn from(s: Session) -> interop::Session {
+        interop::Session {
+            id: s.id,
+            user_agent: s.user_agent,
+            ip_address: s.ip_address,
+            created_at: s.created_at,
+            last_seen_at: s.last_seen_at,
+            current: false,
+        }
+    }
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "sessions")]
+struct SessionOwner {
+    id: Key,
+    user_id: Key,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "sessions")]
+struct SessionId {
+    id: Key,
+}
+
+pub(crate) async fn create(
+    db_pool: &Pool,
+    token: &str,
+    user_id: Key,
+    user_agent: Option<&str>,
+    ip_address: Option<&str>,
+) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        &format!(
+            "INSERT INTO sessions ( token_hash, user_id, user_agent, ip_address )
+             VALUES ( {}, $2, $3, $4 )",
+            TOKEN_HASH
+        ),
+        &[&token, &user_id, &user_agent, &ip_address],
+    )
+    .await
+}
+
+// returns (session id, user id) for a session that hasn't been idle for
+// longer than idle_days and belongs to an enabled user, bumping its last
+// seen time
+//
+pub(crate) async fn touch(db_pool: &Pool, token: &str, idle_days: i32) -> Result<(Key, Key)> {
+    let owner = pg::one_non_transactional::<SessionOwner>(
+        db_pool,
+        &format!(
+            "UPDATE sessions s
+             SET last_seen_at = now()
+             FROM users u
+             WHERE s.token_hash = {}
+                   AND s.last_seen_at > now() - make_interval(days => $2::INTEGER)
+                   AND u.id = s.user_id AND u.disabled = false
+             RETURNING s.id, s.user_id",
+            TOKEN_HASH
+        ),
+        &[&token, &idle_days],
+    )
+    .await?;
+
+    Ok((owner.id, owner.user_id))
+}
+
+pub(crate) async fn all(db_pool: &Pool, user_id: Key) -> Result<Vec<interop::Session>> {
+    pg::many_from::<Session, interop::Session>(
+        db_pool,
+        "SELECT $table_fields
+         FROM sessions
+         WHERE user_id = $1
+         ORDER BY last_seen_at desc",
+        &[&user_id],
+    )
+    .await
+}
+
+pub(crate) async fn delete(db_pool: &Pool, user_id: Key, session_id: Key) -> Result<()> {
+    pg::one_non_transactional::<SessionId>(
+        db_pool,
+        "DELETE FROM sessions
+         WHERE id = $1 AND user_id = $2
+         RETURNING id",
+        &[&session_id, &user_id],
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn delete_by_token(db_pool: &Pool, token: &str) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        &format!("DELETE FROM sessions WHERE token_hash = {}", TOKEN_HASH),
+        &[&token],
+    )
+    .await
+}
+
+pub(crate) async fn delete_all(db_pool: &Pool, user_id: Key) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        "DELETE FROM sessions
+         WHERE user_id = $1",
+        &[&user_id],
+    )
+    .await
+}
+
+// returns the number of sessions that were deleted
+//
+pub(crate) async fn purge_idle(db_pool: &Pool, idle_days: i32) -> Result<usize> {
+    let purged = pg::many_from::<SessionId, SessionId>(
+        db_pool,
+        "DELETE FROM sessions
+         WHERE last_seen_at < now() - make_interval(days => $1::INTEGER)
+         RETURNING id",
+        &[&idle_days],
+    )
+    .await?;
+
+    Ok(purged.len())
+}
@@ -0,0 +1,155 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use super::pg;
+use crate::error::Result;
+use crate::interop::tags as interop;
+use crate::interop::Key;
+use deadpool_postgres::{Pool, Transaction};
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper_derive::PostgresMapper;
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "tags")]
+struct Tag {
+    id: Key,
+    title: String,
+    note_count: i64,
+}
+
+impl From<Tag> for interop::Tag {
+    fn from(tag: Tag) -> interop::Tag {
+        interop::Tag {
+            id: tag.id,
+            title: tag.title,
+            note_count: tag.note_count,
+        }
+    }
+}
+
+pub(crate) async fn create(
+    db_pool: &Pool,
+    user_id: Key,
+    tag: &interop::ProtoTag,
+) -> Result<interop::Tag> {
+    pg::one_from::<Tag, interop::Tag>(
+        db_pool,
+        "INSERT INTO tags(user_id, title)
+         VALUES ($1, $2)
+         RETURNING id, title, 0::BIGINT AS note_count",
+        &[&user_id, &tag.title],
+    )
+    .await
+}
+
+const ALL: &str = "
+    SELECT t.id,
+           t.title,
+           count(n.id) AS note_count
+    FROM   tags t
+           LEFT JOIN note_tags nt ON nt.tag_id = t.id
+           LEFT JOIN notes n ON n.id = nt.note_id AND n.deleted_at is null
+    WHERE  t.user_id = $1
+    GROUP BY t.id, t.title
+    ORDER BY t.title asc";
+
+pub(crate) async fn all(db_pool: &Pool, user_id: Key) -> Result<Vec<interop::Tag>> {
+    pg::many_from::<Tag, interop::Tag>(db_pool, ALL, &[&user_id]).await
+}
+
+pub(crate) async fn all_in_tx(tx: &Transaction<'_>, user_id: Key) -> Result<Vec<interop::Tag>> {
+    pg::many::<Tag, interop::Tag>(tx, ALL, &[&user_id]).await
+}
+
+pub(crate) async fn get(db_pool: &Pool, user_id: Key, tag_id: Key) -> Result<interop::Tag> {
+    pg::one_from::<Tag, interop::Tag>(
+        db_pool,
+        "SELECT t.id,
+                t.title,
+                count(n.id) AS note_count
+         FROM   tags t
+                LEFT JOIN note_tags nt ON nt.tag_id = t.id
+                LEFT JOIN notes n ON n.id = nt.note_id AND n.deleted_at is null
+         WHERE  t.user_id = $1 AND t.id = $2
+         GROUP BY t.id, t.title",
+        &[&user_id, &tag_id],
+    )
+    .await
+}
+
+pub(crate) async fn edit(
+    db_pool: &Pool,
+    user_id: Key,
+    tag: &interop::ProtoTag,
+    tag_id: Key,
+) -> Result<interop::Tag> {
+    pg::zero_from(
+        db_pool,
+        "UPDATE tags
+         SET title = $3
+         WHERE id = $2 and user_id = $1",
+        &[&user_id, &tag_id, &tag.title],
+    )
+    .await?;
+
+    get(db_pool, user_id, tag_id).await
+}
+
+// any note_tags rows referencing the tag are removed by the cascade
+//
+pub(crate) async fn delete(db_pool: &Pool, user_id: Key, tag_id: Key) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        "DELETE FROM tags
+         WHERE user_id = $1 AND id = $2",
+        &[&user_id, &tag_id],
+    )
+    .await
+}
+
+pub(crate) async fn add_to_note(
+    db_pool: &Pool,
+    user_id: Key,
+    note_id: Key,
+    tag_id: Key,
+) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        "INSERT INTO note_tags(note_id, tag_id)
+         SELECT n.id, t.id
+         FROM   notes n, tags t
+         WHERE  n.id = $2 AND n.user_id = $1 AND t.id = $3 AND t.user_id = $1
+         ON CONFLICT DO NOTHING",
+        &[&user_id, &note_id, &tag_id],
+    )
+    .await
+}
+
+pub(crate) async fn remove_from_note(
+    db_pool: &Pool,
+    user_id: Key,
+    note_id: Key,
+    tag_id: Key,
+) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        "DELETE FROM note_tags nt
+         USING  notes n
+         WHERE  nt.note_id = n.id AND n.user_id = $1 AND nt.note_id = $2 AND nt.tag_id = $3",
+        &[&user_id, &note_id, &tag_id],
+    )
+    .await
+}
@@ -0,0 +1,125 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{pg, TOKEN_HASH};
+use crate::error::Result;
+use crate::interop::tokens as interop;
+use crate::interop::Key;
+use deadpool_postgres::Pool;
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper_derive::PostgresMapper;
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "api_tokens")]
+struct Token {
+    id: Key,
+    name: String,
+    scope: interop::TokenScope,
+    created_at: chrono::DateTime<chrono::Utc>,
+    last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<Token> for interop::Token {
+    fn from(t: Token) -> interop::Token {
+        interop::Token {
+            id: t.id,
+            name: t.name,
+            scope: t.scope,
+            created_at: t.created_at,
+            last_used_at: t.last_used_at,
+        }
+    }
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "api_tokens")]
+struct TokenOwner {
+    user_id: Key,
+    scope: interop::TokenScope,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "api_tokens")]
+struct TokenId {
+    id: Key,
+}
+
+pub(crate) async fn create(
+    db_pool: &Pool,
+    secret: &str,
+    user_id: Key,
+    token: &interop::ProtoToken,
+) -> Result<interop::Token> {
+    pg::one_from::<Token, interop::Token>(
+        db_pool,
+        &format!(
+            "INSERT INTO api_tokens ( token_hash, user_id, name, scope )
+             VALUES ( {}, $2, $3, $4 )
+             RETURNING $table_fields",
+            TOKEN_HASH
+        ),
+        &[&secret, &user_id, &token.name, &token.scope],
+    )
+    .await
+}
+
+pub(crate) async fn all(db_pool: &Pool, user_id: Key) -> Result<Vec<interop::Token>> {
+    pg::many_from::<Token, interop::Token>(
+        db_pool,
+        "SELECT $table_fields
+         FROM api_tokens
+         WHERE user_id = $1
+         ORDER BY id",
+        &[&user_id],
+    )
+    .await
+}
+
+pub(crate) async fn delete(db_pool: &Pool, user_id: Key, token_id: Key) -> Result<()> {
+    pg::one_non_transactional::<TokenId>(
+        db_pool,
+        "DELETE FROM api_tokens
+         WHERE id = $1 AND user_id = $2
+         RETURNING id",
+        &[&token_id, &user_id],
+    )
+    .await?;
+
+    Ok(())
+}
+
+// returns the owner and scope of the token if it belongs to an enabled user,
+// recording when it was last used
+//
+pub(crate) async fn touch(db_pool: &Pool, secret: &str) -> Result<(Key, interop::TokenScope)> {
+    let owner = pg::one_non_transactional::<TokenOwner>(
+        db_pool,
+        &format!(
+            "UPDATE api_tokens t
+             SET last_used_at = now()
+             FROM users u
+             WHERE t.token_hash = {}
+                   AND u.id = t.user_id AND u.disabled = false
+             RETURNING t.user_id, t.scope",
+            TOKEN_HASH
+        ),
+        &[&secret],
+    )
+    .await?;
+
+    Ok((owner.user_id, owner.scope))
+}
@@ -0,0 +1,177 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use super::{pg, TOKEN_HASH};
+use crate::error::{Error, Result};
+use crate::interop::Key;
+use deadpool_postgres::{Client, Pool};
+use serde::{Deserialize, Serialize};
+use tokio_pg_mapper_derive::PostgresMapper;
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "users")]
+struct Totp {
+    totp_secret: Option<Vec<u8>>,
+    totp_enabled: bool,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "users")]
+struct UserId {
+    id: Key,
+}
+
+// returns the user's secret, if they have one, and whether 2FA is enabled
+//
+pub(crate) async fn get(db_pool: &Pool, user_id: Key) -> Result<(Option<Vec<u8>>, bool)> {
+    let totp = pg::one_non_transactional::<Totp>(
+        db_pool,
+        "SELECT totp_secret, totp_enabled
+         FROM users
+         WHERE id = $1",
+        &[&user_id],
+    )
+    .await?;
+
+    Ok((totp.totp_secret, totp.totp_enabled))
+}
+
+// stores a new secret and set of recovery codes, 2FA stays disabled until a
+// code generated from the secret has been verified. fails with
+// Error::InvalidResource if 2FA is already enabled
+//
+pub(crate) async fn enrol(
+    db_pool: &Pool,
+    user_id: Key,
+    secret: &[u8],
+    recovery_codes: &[String],
+) -> Result<()> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    pg::one::<UserId, UserId>(
+        &tx,
+        "UPDATE users
+         SET totp_secret = $2, totp_last_step = null
+         WHERE id = $1 AND totp_enabled = false
+         RETURNING id",
+        &[&user_id, &secret],
+    )
+    .await
+    .map_err(|e| match e {
+        Error::NotFound => Error::InvalidResource,
+        _ => e,
+    })?;
+
+    pg::zero(
+        &tx,
+        "DELETE FROM recovery_codes
+         WHERE user_id = $1",
+        &[&user_id],
+    )
+    .await?;
+
+    for code in recovery_codes {
+        pg::zero(
+            &tx,
+            &format!(
+                "INSERT INTO recovery_codes ( code_hash, user_id )
+                 VALUES ( {}, $2 )",
+                TOKEN_HASH
+            ),
+            &[&code, &user_id],
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+pub(crate) async fn enable(db_pool: &Pool, user_id: Key, step: i64) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        "UPDATE users
+         SET totp_enabled = true, totp_last_step = $2
+         WHERE id = $1 AND totp_enabled = false",
+        &[&user_id, &step],
+    )
+    .await
+}
+
+// records the time step of an accepted code, returns Error::NotFound if a
+// code from this or a later step has already been used
+//
+pub(crate) async fn use_step(db_pool: &Pool, user_id: Key, step: i64) -> Result<()> {
+    pg::one_non_transactional::<UserId>(
+        db_pool,
+        "UPDATE users
+         SET totp_last_step = $2
+         WHERE id = $1 AND (totp_last_step is null OR totp_last_step < $2)
+         RETURNING id",
+        &[&user_id, &step],
+    )
+    .await?;
+
+    Ok(())
+}
+
+// returns Error::NotFound if the code doesn't match an unused recovery code
+//
+pub(crate) async fn use_recovery_code(db_pool: &Pool, user_id: Key, code: &str) -> Result<()> {
+    pg::one_non_transactional::<UserId>(
+        db_pool,
+        &format!(
+            "UPDATE recovery_codes
+             SET used_at = now()
+             WHERE code_hash = {} AND user_id = $2 AND used_at is null
+             RETURNING user_id AS id",
+            TOKEN_HASH
+        ),
+        &[&code, &user_id],
+    )
+    .await?;
+
+    Ok(())
+}
+
+pub(crate) async fn disable(db_pool: &Pool, user_id: Key) -> Result<()> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    pg::zero(
+        &tx,
+        "UPDATE users
+         SET totp_secret = null, totp_enabled = false, totp_last_step = null
+         WHERE id = $1",
+        &[&user_id],
+    )
+    .await?;
+
+    pg::zero(
+        &tx,
+        "DELETE FROM recovery_codes
+         WHERE user_id = $1",
+        &[&user_id],
+    )
+    .await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
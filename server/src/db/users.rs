@@ -15,11 +15,13 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
-use super::pg;
-use crate::error::Result;
+use super::{pg, TOKEN_HASH};
+use crate::error::{Error, Result};
+use crate::interop::export::ExportedUser;
 use crate::interop::users as interop;
 use crate::interop::Key;
-use deadpool_postgres::Pool;
+use crate::throttle;
+use deadpool_postgres::{Client, Pool, Transaction};
 use serde::{Deserialize, Serialize};
 use tokio_pg_mapper_derive::PostgresMapper;
 
@@ -30,6 +32,126 @@ struct User {
     email: String,
     username: String,
     password: String,
+    role: interop::UserRole,
+    disabled: bool,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "users")]
+struct UserId {
+    id: Key,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "users")]
+struct UserSummary {
+    id: Key,
+    username: String,
+    email: String,
+    role: interop::UserRole,
+    disabled: bool,
+    created_at: chrono::DateTime<chrono::Utc>,
+    inbox_notes: i64,
+    triaged_notes: i64,
+    binned_notes: i64,
+}
+
+impl From<UserSummary> for interop::UserSummary {
+    fn from(u: UserSummary) -> interop::UserSummary {
+        interop::UserSummary {
+            id: u.id,
+            username: u.username,
+            email: u.email,
+            role: u.role,
+            disabled: u.disabled,
+            created_at: u.created_at,
+            inbox_notes: u.inbox_notes,
+            triaged_notes: u.triaged_notes,
+            binned_notes: u.binned_notes,
+        }
+    }
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "users")]
+struct UserDetails {
+    username: String,
+    email: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+impl From<UserDetails> for ExportedUser {
+    fn from(u: UserDetails) -> ExportedUser {
+        ExportedUser {
+            username: u.username,
+            email: u.email,
+            created_at: u.created_at,
+        }
+    }
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "users")]
+struct AdminCount {
+    admins: i64,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "users")]
+struct Role {
+    role: interop::UserRole,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "users")]
+struct Password {
+    password: String,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "password_resets")]
+struct PasswordReset {
+    user_id: Key,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "failed_logins")]
+struct FailedLoginId {
+    id: Key,
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "users")]
+struct BinRetention {
+    bin_retention_days: Option<i32>,
+}
+
+impl From<BinRetention> for interop::BinRetention {
+    fn from(b: BinRetention) -> interop::BinRetention {
+        interop::BinRetention {
+            days: b.bin_retention_days,
+        }
+    }
+}
+
+#[derive(Deserialize, PostgresMapper, Serialize)]
+#[pg_mapper(table = "invites")]
+struct Invite {
+    id: Key,
+    code: String,
+    expires_at: chrono::DateTime<chrono::Utc>,
+    used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl From<Invite> for interop::Invite {
+    fn from(i: Invite) -> interop::Invite {
+        interop::Invite {
+            id: i.id,
+            code: i.code,
+            expires_at: i.expires_at,
+            used_at: i.used_at,
+        }
+    }
 }
 
 impl From<User> for interop::User {
@@ -37,7 +159,7 @@ impl From<User> for interop::User {
         interop::User {
             username: user.username,
             email: user.email,
-            admin: None,
+            role: user.role,
         }
     }
 }
@@ -50,7 +172,7 @@ pub(crate) async fn login(
         db_pool,
         "SELECT $table_fields
          FROM users
-         WHERE email = $1",
+         WHERE email = $1 AND disabled = false",
         &[&login_credentials.email],
     )
     .await?;
@@ -60,13 +182,40 @@ pub(crate) async fn login(
     Ok((db_user.id, password, interop::User::from(db_user)))
 }
 
+// if the registration has an invite code it's used up as part of creating
+// the user, an unknown, expired or already used code fails with
+// Error::RegistrationDenied
+//
 pub(crate) async fn create(
     db_pool: &Pool,
     registration: &interop::Registration,
     hash: &str,
 ) -> Result<(Key, interop::User)> {
-    let db_user = pg::one_non_transactional::<User>(
-        db_pool,
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    let invite = if let Some(code) = &registration.invite_code {
+        let invite = pg::one::<Invite, Invite>(
+            &tx,
+            "UPDATE invites
+             SET used_at = now()
+             WHERE code = $1 AND used_at is null AND expires_at > now()
+             RETURNING $table_fields",
+            &[&code],
+        )
+        .await
+        .map_err(|e| match e {
+            Error::NotFound => Error::RegistrationDenied,
+            _ => e,
+        })?;
+        Some(invite)
+    } else {
+        None
+    };
+
+    // admins are only ever made by an operator (`memo_server create-user --admin`)
+    let db_user = pg::one::<User, User>(
+        &tx,
         "INSERT INTO users ( username, email, password )
          VALUES ( $1, $2, $3 )
          RETURNING $table_fields",
@@ -74,16 +223,407 @@ pub(crate) async fn create(
     )
     .await?;
 
+    if let Some(invite) = invite {
+        pg::zero(
+            &tx,
+            "UPDATE invites
+             SET used_by = $2
+             WHERE id = $1",
+            &[&invite.id, &db_user.id],
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
     Ok((db_user.id, interop::User::from(db_user)))
 }
 
+pub(crate) async fn create_invite(
+    db_pool: &Pool,
+    admin_id: Key,
+    code: &str,
+    expires_in_hours: i32,
+) -> Result<interop::Invite> {
+    pg::one_from::<Invite, interop::Invite>(
+        db_pool,
+        "INSERT INTO invites ( created_by, code, expires_at )
+         VALUES ( $1, $2, now() + make_interval(hours => $3::INTEGER) )
+         RETURNING $table_fields",
+        &[&admin_id, &code, &expires_in_hours],
+    )
+    .await
+}
+
+pub(crate) async fn all_invites(db_pool: &Pool) -> Result<Vec<interop::Invite>> {
+    pg::many_from::<Invite, interop::Invite>(
+        db_pool,
+        "SELECT $table_fields
+         FROM invites
+         ORDER BY id desc",
+        &[],
+    )
+    .await
+}
+
+pub(crate) async fn delete_invite(db_pool: &Pool, invite_id: Key) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        "DELETE FROM invites
+         WHERE id = $1 AND used_at is null",
+        &[&invite_id],
+    )
+    .await
+}
+
 pub(crate) async fn get(db_pool: &Pool, user_id: Key) -> Result<interop::User> {
     pg::one_from::<User, interop::User>(
         db_pool,
         "SELECT $table_fields
+         FROM users
+         WHERE id = $1 AND disabled = false",
+        &[&user_id],
+    )
+    .await
+}
+
+pub(crate) async fn get_bin_retention(
+    db_pool: &Pool,
+    user_id: Key,
+) -> Result<interop::BinRetention> {
+    pg::one_from::<BinRetention, interop::BinRetention>(
+        db_pool,
+        "SELECT bin_retention_days
          FROM users
          WHERE id = $1",
-        &[&user_id]
+        &[&user_id],
+    )
+    .await
+}
+
+pub(crate) async fn set_bin_retention(
+    db_pool: &Pool,
+    user_id: Key,
+    retention: &interop::BinRetention,
+) -> Result<interop::BinRetention> {
+    pg::one_from::<BinRetention, interop::BinRetention>(
+        db_pool,
+        "UPDATE users
+         SET bin_retention_days = $2
+         WHERE id = $1
+         RETURNING bin_retention_days",
+        &[&user_id, &retention.days],
     )
     .await
 }
+
+pub(crate) async fn role(db_pool: &Pool, user_id: Key) -> Result<interop::UserRole> {
+    let role = pg::one_non_transactional::<Role>(
+        db_pool,
+        "SELECT role
+         FROM users
+         WHERE id = $1 AND disabled = false",
+        &[&user_id],
+    )
+    .await?;
+
+    Ok(role.role)
+}
+
+pub(crate) async fn all_summaries(db_pool: &Pool) -> Result<Vec<interop::UserSummary>> {
+    pg::many_from::<UserSummary, interop::UserSummary>(
+        db_pool,
+        "SELECT u.id,
+                u.username,
+                u.email,
+                u.role,
+                u.disabled,
+                u.created_at,
+                count(n.id) FILTER (WHERE n.triaged_at is null AND n.deleted_at is null) AS inbox_notes,
+                count(n.id) FILTER (WHERE n.triaged_at is not null AND n.deleted_at is null) AS triaged_notes,
+                count(n.id) FILTER (WHERE n.deleted_at is not null) AS binned_notes
+         FROM   users u
+                LEFT JOIN notes n ON n.user_id = u.id
+         GROUP BY u.id
+         ORDER BY u.id",
+        &[],
+    )
+    .await
+}
+
+pub(crate) async fn set_disabled(db_pool: &Pool, user_id: Key, disabled: bool) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        "UPDATE users
+         SET disabled = $2
+         WHERE id = $1",
+        &[&user_id, &disabled],
+    )
+    .await
+}
+
+// disabled users are included, this is only used by the command line tools
+//
+pub(crate) async fn id_by_email(db_pool: &Pool, email: &str) -> Result<Key> {
+    let user = pg::one_non_transactional::<UserId>(
+        db_pool,
+        "SELECT id
+         FROM users
+         WHERE email = $1",
+        &[&email],
+    )
+    .await?;
+
+    Ok(user.id)
+}
+
+pub(crate) async fn set_role(db_pool: &Pool, user_id: Key, role: interop::UserRole) -> Result<()> {
+    pg::zero_from(
+        db_pool,
+        "UPDATE users
+         SET role = $2
+         WHERE id = $1",
+        &[&user_id, &role],
+    )
+    .await
+}
+
+pub(crate) async fn password(db_pool: &Pool, user_id: Key) -> Result<String> {
+    let password = pg::one_non_transactional::<Password>(
+        db_pool,
+        "SELECT password
+         FROM users
+         WHERE id = $1",
+        &[&user_id],
+    )
+    .await?;
+
+    Ok(password.password)
+}
+
+// changing the password ends every one of the user's sessions
+//
+// every session and any outstanding password reset is ended, an admin
+// resetting the password can also revoke the user's api tokens
+//
+pub(crate) async fn set_password(
+    db_pool: &Pool,
+    user_id: Key,
+    hash: &str,
+    revoke_api_tokens: bool,
+) -> Result<()> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    pg::zero(
+        &tx,
+        "UPDATE users
+         SET password = $2
+         WHERE id = $1",
+        &[&user_id, &hash],
+    )
+    .await?;
+
+    end_sessions_and_resets(&tx, user_id).await?;
+
+    if revoke_api_tokens {
+        pg::zero(
+            &tx,
+            "DELETE FROM api_tokens
+             WHERE user_id = $1",
+            &[&user_id],
+        )
+        .await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+// returns Error::NotFound if there's no enabled user with the given email
+//
+pub(crate) async fn create_password_reset(
+    db_pool: &Pool,
+    email: &str,
+    token: &str,
+    expires_in_minutes: i32,
+) -> Result<()> {
+    pg::one_non_transactional::<PasswordReset>(
+        db_pool,
+        &format!(
+            "INSERT INTO password_resets ( user_id, token_hash, expires_at )
+             SELECT id, {}, now() + make_interval(mins => $3::INTEGER)
+             FROM users
+             WHERE email = $2 AND disabled = false
+             RETURNING user_id",
+            TOKEN_HASH
+        ),
+        &[&token, &email, &expires_in_minutes],
+    )
+    .await?;
+
+    Ok(())
+}
+
+// uses up the reset token and sets the new password, an unknown, expired or
+// already used token fails with Error::InvalidResetToken
+//
+pub(crate) async fn reset_password(db_pool: &Pool, token: &str, hash: &str) -> Result<()> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    let reset = pg::one::<PasswordReset, PasswordReset>(
+        &tx,
+        &format!(
+            "UPDATE password_resets
+             SET used_at = now()
+             WHERE token_hash = {} AND used_at is null AND expires_at > now()
+             RETURNING user_id",
+            TOKEN_HASH
+        ),
+        &[&token],
+    )
+    .await
+    .map_err(|e| match e {
+        Error::NotFound => Error::InvalidResetToken,
+        _ => e,
+    })?;
+
+    pg::zero(
+        &tx,
+        "UPDATE users
+         SET password = $2
+         WHERE id = $1",
+        &[&reset.user_id, &hash],
+    )
+    .await?;
+
+    end_sessions_and_resets(&tx, reset.user_id).await?;
+
+    tx.commit().await?;
+
+    Ok(())
+}
+
+async fn end_sessions_and_resets(tx: &Transaction<'_>, user_id: Key) -> Result<()> {
+    pg::zero(
+        tx,
+        "DELETE FROM sessions
+         WHERE user_id = $1",
+        &[&user_id],
+    )
+    .await?;
+
+    pg::zero(
+        tx,
+        "DELETE FROM password_resets
+         WHERE user_id = $1",
+        &[&user_id],
+    )
+    .await
+}
+
+// a locked out email would otherwise add a row for every attempt made
+// while it's locked, only the first one in each lockout period is kept
+//
+pub(crate) async fn record_failed_login(
+    db_pool: &Pool,
+    email: &str,
+    ip_address: Option<&str>,
+    locked_out: bool,
+) -> Result<()> {
+    let lockout_secs = throttle::MAX_LOCKOUT.as_secs() as i32;
+    pg::zero_from(
+        db_pool,
+        "INSERT INTO failed_logins ( email, user_id, ip_address, locked_out )
+         SELECT $1, (SELECT id FROM users WHERE email = $1), $2, $3
+         WHERE NOT $3
+               OR NOT EXISTS (SELECT 1
+                              FROM failed_logins f
+                              WHERE f.email = $1
+                                    AND f.locked_out
+                                    AND f.created_at > now() - make_interval(secs => $4::INTEGER))",
+        &[&email, &ip_address, &locked_out, &lockout_secs],
+    )
+    .await
+}
+
+// returns the number of failed logins that were deleted
+//
+pub(crate) async fn purge_failed_logins(db_pool: &Pool, days: i32) -> Result<usize> {
+    let purged = pg::many_from::<FailedLoginId, FailedLoginId>(
+        db_pool,
+        "DELETE FROM failed_logins
+         WHERE created_at < now() - make_interval(days => $1::INTEGER)
+         RETURNING id",
+        &[&days],
+    )
+    .await?;
+
+    Ok(purged.len())
+}
+
+pub(crate) async fn export_details(tx: &Transaction<'_>, user_id: Key) -> Result<ExportedUser> {
+    pg::one::<UserDetails, ExportedUser>(
+        tx,
+        "SELECT username, email, created_at
+         FROM users
+         WHERE id = $1",
+        &[&user_id],
+    )
+    .await
+}
+
+// removes the user along with everything they own in a single transaction.
+// the last admin can't delete themselves, that fails with Error::Forbidden
+//
+pub(crate) async fn delete(db_pool: &Pool, user_id: Key) -> Result<()> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    let tx = client.transaction().await?;
+
+    let other_admins = pg::one::<AdminCount, AdminCount>(
+        &tx,
+        "SELECT count(*) AS admins
+         FROM users
+         WHERE role = 'admin' AND disabled = false AND id <> $1",
+        &[&user_id],
+    )
+    .await?;
+    let user = pg::one::<Role, Role>(
+        &tx,
+        "SELECT role
+         FROM users
+         WHERE id = $1",
+        &[&user_id],
+    )
+    .await?;
+    if user.role == interop::UserRole::Admin && other_admins.admins == 0 {
+        return Err(Error::Forbidden);
+    }
+
+    // invites that others registered with remain, only the link to this
+    // user is removed
+    let statements = [
+        "UPDATE invites SET used_by = null WHERE used_by = $1",
+        "DELETE FROM invites WHERE created_by = $1",
+        "DELETE FROM failed_logins WHERE user_id = $1",
+        "DELETE FROM recovery_codes WHERE user_id = $1",
+        "DELETE FROM api_tokens WHERE user_id = $1",
+        "DELETE FROM sessions WHERE user_id = $1",
+        "DELETE FROM password_resets WHERE user_id = $1",
+        "DELETE FROM triage_rules WHERE user_id = $1",
+        "DELETE FROM note_revisions WHERE user_id = $1",
+        "DELETE FROM notes WHERE user_id = $1",
+        "DELETE FROM tags WHERE user_id = $1",
+        "DELETE FROM categories WHERE user_id = $1",
+        "DELETE FROM users WHERE id = $1",
+    ];
+    for statement in statements.iter() {
+        pg::zero(&tx, statement, &[&user_id]).await?;
+    }
+
+    tx.commit().await?;
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// line-level diff based on the longest common subsequence of the two texts
+//
+// the LCS table is quadratic in size so if the differing middle section of
+// the two texts is too large the diff degrades to deleting every old line
+// and inserting every new one
+//
+const MAX_TABLE_SIZE: usize = 4_000_000;
+
+#[derive(Clone, Copy, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Op {
+    Equal,
+    Insert,
+    Delete,
+}
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct Line {
+    pub op: Op,
+    pub text: String,
+}
+
+pub fn lines(from: &str, to: &str) -> Vec<Line> {
+    let a: Vec<&str> = from.lines().collect();
+    let b: Vec<&str> = to.lines().collect();
+
+    // strip the common prefix and suffix, most edits only touch a few lines
+    let prefix = a.iter().zip(b.iter()).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+
+    let a_mid = &a[prefix..a.len() - suffix];
+    let b_mid = &b[prefix..b.len() - suffix];
+
+    let mut res = Vec::with_capacity(a.len().max(b.len()));
+
+    push_all(&mut res, Op::Equal, &a[..prefix]);
+    if a_mid.len() * b_mid.len() > MAX_TABLE_SIZE {
+        push_all(&mut res, Op::Delete, a_mid);
+        push_all(&mut res, Op::Insert, b_mid);
+    } else {
+        lcs(&mut res, a_mid, b_mid);
+    }
+    push_all(&mut res, Op::Equal, &a[a.len() - suffix..]);
+
+    res
+}
+
+fn lcs(res: &mut Vec<Line>, a: &[&str], b: &[&str]) {
+    let n = a.len();
+    let m = b.len();
+    let idx = |i: usize, j: usize| i * (m + 1) + j;
+
+    // table[idx(i, j)] is the length of the LCS of a[i..] and b[j..]
+    let mut table = vec![0u32; (n + 1) * (m + 1)];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            table[idx(i, j)] = if a[i] == b[j] {
+                table[idx(i + 1, j + 1)] + 1
+            } else {
+                table[idx(i + 1, j)].max(table[idx(i, j + 1)])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            push(res, Op::Equal, a[i]);
+            i += 1;
+            j += 1;
+        } else if table[idx(i + 1, j)] >= table[idx(i, j + 1)] {
+            push(res, Op::Delete, a[i]);
+            i += 1;
+        } else {
+            push(res, Op::Insert, b[j]);
+            j += 1;
+        }
+    }
+    push_all(res, Op::Delete, &a[i..]);
+    push_all(res, Op::Insert, &b[j..]);
+}
+
+fn push(res: &mut Vec<Line>, op: Op, text: &str) {
+    res.push(Line {
+        op,
+        text: text.to_string(),
+    });
+}
+
+fn push_all(res: &mut Vec<Line>, op: Op, texts: &[&str]) {
+    for text in texts {
+        push(res, op, text);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ops(diff: &[Line]) -> String {
+        diff.iter()
+            .map(|l| match l.op {
+                Op::Equal => '=',
+                Op::Insert => '+',
+                Op::Delete => '-',
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_identical() {
+        let diff = lines("a\nb\nc", "a\nb\nc");
+        assert_eq!(ops(&diff), "===");
+    }
+
+    #[test]
+    fn test_changed_middle_line() {
+        let diff = lines("a\nb\nc", "a\nx\nc");
+        assert_eq!(ops(&diff), "=-+=");
+        assert_eq!(diff[1].text, "b");
+        assert_eq!(diff[2].text, "x");
+    }
+
+    #[test]
+    fn test_insert_and_delete() {
+        assert_eq!(ops(&lines("a\nc", "a\nb\nc")), "=+=");
+        assert_eq!(ops(&lines("a\nb\nc", "a\nc")), "=-=");
+        assert_eq!(ops(&lines("", "a\nb")), "++");
+        assert_eq!(ops(&lines("a\nb", "")), "--");
+    }
+
+    #[test]
+    fn test_reordered_lines() {
+        let diff = lines("a\nb\nc\nd", "b\na\nd\nc");
+        let kept: Vec<&str> = diff
+            .iter()
+            .filter(|l| l.op == Op::Equal)
+            .map(|l| l.text.as_str())
+            .collect();
+        assert_eq!(kept.len(), 2);
+    }
+}
@@ -15,8 +15,13 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::interop::categories::CategoryInUse;
+use crate::interop::errors::{ApiError, FieldError};
+use actix_web::http::{header, StatusCode};
 use actix_web::{HttpResponse, ResponseError};
 use derive_more::{Display, From};
+use tokio_postgres::error::SqlState;
+use tracing::error;
 
 pub type Result<T> = ::std::result::Result<T, Error>;
 
@@ -37,21 +42,207 @@ pub enum Error {
     ParseInt(std::num::ParseIntError),
     SerdeJson(serde_json::Error),
     Authenticating,
+    Forbidden,
+    #[from(ignore)]
+    TooManyAttempts(u64),
+    RegistrationDenied,
+    #[from(ignore)]
+    Configuration(String),
+    #[from(ignore)]
+    Usage(String),
+    CategoryCycle,
+    #[from(ignore)]
+    CategoryInUse(i64),
     MissingField,
     ModelConversion,
     ModelNonUniqueTableName,
     InvalidKind,
     InvalidResource,
+    #[from(ignore)]
+    InvalidRequest(String),
+    #[from(ignore)]
+    #[display(fmt = "Validation({:?})", _0)]
+    Validation(Vec<FieldError>),
+    PayloadTooLarge,
+    InvalidResetToken,
+    InvalidTotpCode,
     RadixConversion,
     Other,
 }
 
+fn is_unique_violation(e: &tokio_postgres::error::Error) -> bool {
+    e.code() == Some(&SqlState::UNIQUE_VIOLATION)
+}
+
+impl Error {
+    // the name of the variant without its contents, errors are counted by it
+    //
+    pub fn variant(&self) -> String {
+        let debug = format!("{:?}", self);
+        debug
+            .split(|c: char| !c.is_alphanumeric())
+            .next()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    // internal errors are described generically, their details only go to the log
+    //
+    fn api_error(&self, status: StatusCode) -> ApiError {
+        let (code, message) = match self {
+            Error::NotFound => ("not_found", String::from("resource not found")),
+            Error::Authenticating => ("unauthorized", String::from("authentication required")),
+            Error::Forbidden => ("forbidden", String::from("not allowed")),
+            Error::TooManyAttempts(_) => (
+                "too_many_attempts",
+                String::from("too many attempts, try again later"),
+            ),
+            Error::RegistrationDenied => (
+                "registration_denied",
+                String::from("registration is not possible"),
+            ),
+            Error::CategoryCycle => (
+                "category_cycle",
+                String::from("a category can't be moved beneath itself"),
+            ),
+            Error::CategoryInUse(_) => (
+                "category_in_use",
+                String::from("the category still contains notes"),
+            ),
+            Error::MissingField => ("missing_field", String::from("a required field is missing")),
+            Error::InvalidResource => ("invalid_resource", String::from("invalid resource")),
+            Error::InvalidRequest(message) => ("invalid_request", message.clone()),
+            Error::Validation(_) => ("validation_failed", String::from("some fields are invalid")),
+            Error::PayloadTooLarge => ("payload_too_large", String::from("request body too large")),
+            Error::InvalidResetToken => (
+                "invalid_reset_token",
+                String::from("the reset token is invalid or has expired"),
+            ),
+            Error::InvalidTotpCode => ("invalid_totp_code", String::from("invalid code")),
+            Error::Utf8(e) => ("invalid_request", e.to_string()),
+            Error::ParseInt(e) => ("invalid_request", e.to_string()),
+            Error::SerdeJson(e) => ("invalid_request", e.to_string()),
+            Error::RadixConversion => ("invalid_request", String::from("invalid number")),
+            Error::TokioPostgres(_) if status == StatusCode::CONFLICT => {
+                ("conflict", String::from("resource already exists"))
+            }
+            Error::DeadPool(_) => (
+                "unavailable",
+                String::from("the database is unavailable, try again later"),
+            ),
+            Error::Actix(e) if status.is_client_error() => ("invalid_request", e.to_string()),
+            _ => ("internal", String::from("internal server error")),
+        };
+
+        let details = match self {
+            Error::TooManyAttempts(retry_after_secs) => {
+                Some(serde_json::json!({ "retry_after_secs": retry_after_secs }))
+            }
+            Error::CategoryInUse(affected_notes) => serde_json::to_value(CategoryInUse {
+                affected_notes: *affected_notes,
+            })
+            .ok(),
+            Error::Validation(problems) => Some(serde_json::json!({ "fields": problems })),
+            Error::TokioPostgres(e) if status == StatusCode::CONFLICT => e
+                .as_db_error()
+                .and_then(|db_error| db_error.constraint())
+                .map(|constraint| serde_json::json!({ "constraint": constraint })),
+            _ => None,
+        };
+
+        ApiError {
+            code,
+            message,
+            details,
+        }
+    }
+}
+
 impl ResponseError for Error {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Error::NotFound => StatusCode::NOT_FOUND,
+            Error::Authenticating => StatusCode::UNAUTHORIZED,
+            Error::Forbidden => StatusCode::FORBIDDEN,
+            Error::TooManyAttempts(_) => StatusCode::TOO_MANY_REQUESTS,
+            Error::RegistrationDenied => StatusCode::FORBIDDEN,
+            Error::CategoryCycle
+            | Error::MissingField
+            | Error::InvalidResource
+            | Error::InvalidRequest(_)
+            | Error::Validation(_)
+            | Error::InvalidResetToken
+            | Error::InvalidTotpCode
+            | Error::Utf8(_)
+            | Error::ParseInt(_)
+            | Error::SerdeJson(_)
+            | Error::RadixConversion => StatusCode::BAD_REQUEST,
+            Error::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+            Error::CategoryInUse(_) => StatusCode::CONFLICT,
+            Error::TokioPostgres(e) if is_unique_violation(e) => StatusCode::CONFLICT,
+            // the pool is exhausted or the database can't be reached
+            Error::DeadPool(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::Actix(e) => e.as_response_error().status_code(),
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
     fn error_response(&self) -> HttpResponse {
-        match *self {
-            Error::NotFound => HttpResponse::NotFound().finish(),
-            Error::DeadPool(ref err) => HttpResponse::InternalServerError().body(err.to_string()),
-            _ => HttpResponse::InternalServerError().finish(),
+        let status = self.status_code();
+        if status.is_server_error() {
+            error!("{:?}", self);
+        }
+
+        let mut response = HttpResponse::build(status);
+        if let Error::TooManyAttempts(retry_after_secs) = *self {
+            response.header(header::RETRY_AFTER, retry_after_secs.to_string());
         }
+        response.json(self.api_error(status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_codes() {
+        assert_eq!(
+            Error::Authenticating.status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(Error::MissingField.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(
+            Error::InvalidRequest(String::from("bad")).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(Error::CategoryInUse(3).status_code(), StatusCode::CONFLICT);
+        assert_eq!(
+            Error::Other.status_code(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn test_variant() {
+        assert_eq!(Error::NotFound.variant(), "NotFound");
+        assert_eq!(Error::TooManyAttempts(3).variant(), "TooManyAttempts");
+        assert_eq!(
+            Error::Usage(String::from("no such command")).variant(),
+            "Usage"
+        );
+    }
+
+    #[test]
+    fn test_api_error() {
+        let e = Error::CategoryInUse(3).api_error(StatusCode::CONFLICT);
+        assert_eq!(e.code, "category_in_use");
+        assert_eq!(e.details, Some(serde_json::json!({ "affected_notes": 3 })));
+
+        // internals aren't leaked to the client
+        let e = Error::Configuration(String::from("secret"))
+            .api_error(StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(e.code, "internal");
+        assert!(!e.message.contains("secret"));
     }
 }
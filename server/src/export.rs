@@ -0,0 +1,269 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// a user's data as a tar archive: everything in export.json plus a markdown
+// file per note, placed in a directory for the inbox, the bin or the note's
+// category
+//
+use crate::archive::{self, Tar};
+use crate::error::{Error, Result};
+use crate::interop::categories::Category;
+use crate::interop::export::{Export, ExportedNote};
+use crate::interop::Key;
+use std::collections::{HashMap, HashSet};
+
+pub const ROOT: &str = "memo-export";
+
+const MAX_SLUG_LEN: usize = 50;
+// guards against a cycle in the category tree
+const MAX_CATEGORY_DEPTH: usize = 32;
+
+fn slug(title: &str) -> String {
+    let mut slug = String::new();
+    for c in title.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.chars().count() >= MAX_SLUG_LEN {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+
+    if slug.is_empty() {
+        String::from("untitled")
+    } else {
+        String::from(slug)
+    }
+}
+
+fn category_dir(categories: &HashMap<Key, &Category>, id: Key) -> String {
+    let mut parts = vec![];
+    let mut next = Some(id);
+    while let Some(category) = next.and_then(|id| categories.get(&id)) {
+        if parts.len() == MAX_CATEGORY_DEPTH {
+            break;
+        }
+        parts.push(slug(&category.title));
+        next = category.parent_id;
+    }
+    parts.reverse();
+
+    parts.join("/")
+}
+
+fn note_path(categories: &HashMap<Key, &Category>, note: &ExportedNote) -> String {
+    let dir = if note.deleted_at.is_some() {
+        String::from("bin")
+    } else {
+        match (note.triaged_at, note.category_id) {
+            (Some(_), Some(category_id)) => {
+                format!("categories/{}", category_dir(categories, category_id))
+            }
+            _ => String::from("inbox"),
+        }
+    };
+
+    format!("{}/{}/{}-{}.md", ROOT, dir, note.id, slug(&note.title))
+}
+
+fn markdown(note: &ExportedNote) -> String {
+    format!("# {}\n\n{}\n", note.title, note.content)
+}
+
+pub fn archive(export: &Export) -> Result<Vec<u8>> {
+    let mtime = export.exported_at.timestamp();
+    let categories: HashMap<Key, &Category> = export.categories.iter().map(|c| (c.id, c)).collect();
+
+    let mut tar = Tar::new();
+
+    let json = serde_json::to_vec_pretty(export)?;
+    tar.append(&format!("{}/export.json", ROOT), &json, mtime);
+
+    for note in &export.notes {
+        tar.append(
+            &note_path(&categories, note),
+            markdown(note).as_bytes(),
+            note.updated_at.timestamp(),
+        );
+    }
+
+    Ok(tar.finish())
+}
+
+// accepts either an archive created by `archive` or the export.json from one
+//
+pub fn read(bytes: &[u8]) -> Result<Export> {
+    let json = match archive::find(bytes, &format!("{}/export.json", ROOT)) {
+        Some(json) => json,
+        None if bytes.iter().find(|b| !b.is_ascii_whitespace()) == Some(&b'{') => bytes,
+        None => return Err(Error::InvalidResource),
+    };
+
+    Ok(serde_json::from_slice(json)?)
+}
+
+// orders the categories so that every parent comes before its children,
+// categories whose parent isn't in the list are treated as top level
+//
+pub fn parents_first(categories: &[Category]) -> Result<Vec<&Category>> {
+    let ids: HashSet<Key> = categories.iter().map(|c| c.id).collect();
+
+    let mut ordered: Vec<&Category> = vec![];
+    let mut placed: HashSet<Key> = HashSet::new();
+    while ordered.len() < categories.len() {
+        let before = ordered.len();
+        for category in categories {
+            let ready = match category.parent_id {
+                Some(parent_id) => !ids.contains(&parent_id) || placed.contains(&parent_id),
+                None => true,
+            };
+            if ready && !placed.contains(&category.id) {
+                placed.insert(category.id);
+                ordered.push(category);
+            }
+        }
+        if ordered.len() == before {
+            return Err(Error::CategoryCycle);
+        }
+    }
+
+    Ok(ordered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interop::export::ExportedUser;
+
+    fn category(id: Key, title: &str, parent_id: Option<Key>) -> Category {
+        Category {
+            id,
+            title: String::from(title),
+            version: 1,
+            parent_id,
+        }
+    }
+
+    fn note(id: Key, title: &str) -> ExportedNote {
+        let now = chrono::Utc::now();
+        ExportedNote {
+            id,
+            title: String::from(title),
+            content: String::from("content"),
+            category_id: None,
+            tags: vec![],
+            bookmarked: false,
+            version: 1,
+            created_at: now,
+            updated_at: now,
+            triaged_at: None,
+            deleted_at: None,
+            revisions: vec![],
+        }
+    }
+
+    #[test]
+    fn test_slug() {
+        assert_eq!(slug("Hello, World!"), "hello-world");
+        assert_eq!(slug("  --  "), "untitled");
+        assert_eq!(slug("https://example.com/a?b=c"), "https-example-com-a-b-c");
+        assert_eq!(slug("Café Notes"), "café-notes");
+        assert_eq!(slug(&"a".repeat(80)).len(), MAX_SLUG_LEN);
+    }
+
+    #[test]
+    fn test_note_paths() {
+        let categories = [
+            category(1, "Reading List", None),
+            category(2, "Rust", Some(1)),
+            // a cycle shouldn't hang the export
+            category(3, "A", Some(4)),
+            category(4, "B", Some(3)),
+        ];
+        let categories: HashMap<Key, &Category> = categories.iter().map(|c| (c.id, c)).collect();
+
+        let inbox = note(10, "First note");
+        assert_eq!(
+            note_path(&categories, &inbox),
+            "memo-export/inbox/10-first-note.md"
+        );
+
+        let mut triaged = note(11, "Ownership");
+        triaged.triaged_at = Some(chrono::Utc::now());
+        triaged.category_id = Some(2);
+        assert_eq!(
+            note_path(&categories, &triaged),
+            "memo-export/categories/reading-list/rust/11-ownership.md"
+        );
+
+        triaged.deleted_at = Some(chrono::Utc::now());
+        assert_eq!(
+            note_path(&categories, &triaged),
+            "memo-export/bin/11-ownership.md"
+        );
+
+        triaged.deleted_at = None;
+        triaged.category_id = Some(3);
+        assert!(note_path(&categories, &triaged).starts_with("memo-export/categories/"));
+    }
+
+    #[test]
+    fn test_read() {
+        let export = Export {
+            exported_at: chrono::Utc::now(),
+            user: ExportedUser {
+                username: String::from("user"),
+                email: String::from("user@example.com"),
+                created_at: chrono::Utc::now(),
+            },
+            categories: vec![category(1, "Rust", None)],
+            tags: vec![],
+            rules: vec![],
+            notes: vec![note(10, "First note")],
+        };
+
+        let from_archive = read(&archive(&export).unwrap()).unwrap();
+        assert_eq!(from_archive.notes[0].title, "First note");
+
+        let json = serde_json::to_vec(&export).unwrap();
+        assert_eq!(read(&json).unwrap().categories[0].title, "Rust");
+
+        assert!(read(b"neither").is_err());
+    }
+
+    #[test]
+    fn test_parents_first() {
+        let categories = [
+            category(3, "C", Some(2)),
+            category(2, "B", Some(1)),
+            category(1, "A", None),
+            category(4, "orphan", Some(99)),
+        ];
+        let ids: Vec<Key> = parents_first(&categories)
+            .unwrap()
+            .iter()
+            .map(|c| c.id)
+            .collect();
+        assert_eq!(ids, [1, 4, 2, 3]);
+
+        let cycle = [category(1, "A", Some(2)), category(2, "B", Some(1))];
+        assert!(parents_first(&cycle).is_err());
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::db::users as db;
+use crate::error::{Error, Result};
+use crate::handler::random_token;
+use crate::handler::users::hash_password;
+use crate::interop::users as interop;
+use crate::interop::IdParam;
+use crate::session;
+use crate::validation::Validate;
+use crate::ServerConfig;
+use actix_web::web::{Data, Json, Path};
+use actix_web::HttpResponse;
+use deadpool_postgres::Pool;
+
+#[allow(unused_imports)]
+use tracing::info;
+
+const DEFAULT_INVITE_HOURS: i32 = 24 * 7;
+
+pub async fn create_invite(
+    invite: Json<interop::ProtoInvite>,
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("create_invite");
+
+    let admin_id = session::admin_id(&session, &db_pool).await?;
+    let expires_in_hours = invite.expires_in_hours.unwrap_or(DEFAULT_INVITE_HOURS);
+    if expires_in_hours < 1 {
+        return Err(Error::InvalidResource);
+    }
+
+    let code = random_token(16);
+    let invite = db::create_invite(&db_pool, admin_id, &code, expires_in_hours).await?;
+
+    Ok(HttpResponse::Ok().json(invite))
+}
+
+pub async fn get_invites(
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get_invites");
+
+    session::admin_id(&session, &db_pool).await?;
+
+    let invites = db::all_invites(&db_pool).await?;
+
+    Ok(HttpResponse::Ok().json(invites))
+}
+
+pub async fn delete_invite(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("delete_invite {:?}", params.id);
+
+    session::admin_id(&session, &db_pool).await?;
+
+    db::delete_invite(&db_pool, params.id).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+pub async fn get_users(
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get_users");
+
+    session::admin_id(&session, &db_pool).await?;
+
+    let users = db::all_summaries(&db_pool).await?;
+
+    Ok(HttpResponse::Ok().json(users))
+}
+
+pub async fn disable_user(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("disable_user {:?}", params.id);
+
+    let admin_id = session::admin_id(&session, &db_pool).await?;
+
+    // an admin disabling themselves could leave the server without any admins
+    if admin_id == params.id {
+        return Err(Error::InvalidResource);
+    }
+
+    db::set_disabled(&db_pool, params.id, true).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+pub async fn enable_user(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("enable_user {:?}", params.id);
+
+    session::admin_id(&session, &db_pool).await?;
+
+    db::set_disabled(&db_pool, params.id, false).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+pub async fn reset_password(
+    reset: Json<interop::PasswordReset>,
+    db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    params: Path<IdParam>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("reset_password {:?}", params.id);
+
+    session::admin_id(&session, &db_pool).await?;
+
+    reset.validate(&server_config.limits)?;
+
+    let hash = hash_password(&reset.password)?;
+    db::set_password(&db_pool, params.id, &hash, true).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
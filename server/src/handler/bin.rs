@@ -17,32 +17,40 @@
 
 use crate::db::notes as db;
 use crate::error::Result;
-use crate::interop::IdParam;
+use crate::interop::tags::TagFilter;
+use crate::interop::{IdParam, PageParams};
 use crate::session;
-use actix_web::web::{Data, Path};
-use actix_web::HttpResponse;
+use actix_web::web::{Data, Path, Query};
+use actix_web::{HttpRequest, HttpResponse};
 use deadpool_postgres::Pool;
 
 #[allow(unused_imports)]
 use tracing::info;
 
-pub async fn get_all(db_pool: Data<Pool>, session: actix_session::Session) -> Result<HttpResponse> {
+pub async fn get_all(
+    db_pool: Data<Pool>,
+    page: Query<PageParams>,
+    filter: Query<TagFilter>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
     info!("get_all");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
 
-    let notes = db::all_binned(&db_pool, user_id).await?;
+    let notes = db::all_binned(&db_pool, user_id, &page, filter.tag).await?;
 
     Ok(HttpResponse::Ok().json(notes))
 }
 
 pub async fn delete_all(
     db_pool: Data<Pool>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("delete_all");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
 
     db::delete_all(&db_pool, user_id).await?;
 
@@ -52,11 +60,12 @@ pub async fn delete_all(
 pub async fn get(
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("get note {:?}", params.id);
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let note_id = params.id;
 
     let note = db::get(&db_pool, user_id, note_id).await?;
@@ -67,11 +76,12 @@ pub async fn get(
 pub async fn unbin(
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("delete");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let note_id = params.id;
 
     let note = db::unbin(&db_pool, user_id, note_id).await?;
@@ -82,11 +92,12 @@ pub async fn unbin(
 pub async fn delete(
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("delete");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
 
     db::delete(&db_pool, user_id, params.id).await?;
 
@@ -16,12 +16,17 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::db::categories as db;
-use crate::error::Result;
+use crate::db::notes as db_notes;
+use crate::error::{Error, Result};
+use crate::handler::{etag, expected_version};
 use crate::interop::categories as interop;
-use crate::interop::IdParam;
+use crate::interop::tags::TagFilter;
+use crate::interop::{IdParam, PageParams};
 use crate::session;
-use actix_web::web::{Data, Json, Path};
-use actix_web::HttpResponse;
+use crate::validation::Validate;
+use crate::ServerConfig;
+use actix_web::web::{Data, Json, Path, Query};
+use actix_web::{http, HttpRequest, HttpResponse};
 use deadpool_postgres::Pool;
 
 #[allow(unused_imports)]
@@ -30,72 +35,146 @@ use tracing::info;
 pub async fn create(
     category: Json<interop::ProtoCategory>,
     db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("create");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let category = category.into_inner();
+    category.validate(&server_config.limits)?;
 
     info!("{:?}", &category);
 
-    let category = db::create(&db_pool, user_id, &category).await?;
+    let categories = db::create(&db_pool, user_id, &category).await?;
+    let tree = interop::CategoryNode::build_tree(categories);
 
-    Ok(HttpResponse::Ok().json(category))
+    Ok(HttpResponse::Ok().json(tree))
 }
 
-pub async fn get_all(db_pool: Data<Pool>, session: actix_session::Session) -> Result<HttpResponse> {
+pub async fn get_all(
+    db_pool: Data<Pool>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
     info!("get_all");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
 
     let categories = db::all(&db_pool, user_id).await?;
+    let tree = interop::CategoryNode::build_tree(categories);
 
-    Ok(HttpResponse::Ok().json(categories))
+    Ok(HttpResponse::Ok().json(tree))
 }
 
 pub async fn get(
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("get category {:?}", params.id);
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let category_id = params.id;
 
     let category = db::get(&db_pool, user_id, category_id).await?;
 
-    Ok(HttpResponse::Ok().json(category))
+    Ok(HttpResponse::Ok()
+        .header(http::header::ETAG, etag(category.version))
+        .json(category))
 }
 
 pub async fn edit(
     category: Json<interop::ProtoCategory>,
     db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("edit");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let category_id = params.id;
     let category = category.into_inner();
+    category.validate(&server_config.limits)?;
+    let expected_version = expected_version(&req, category.version)?;
+
+    match db::edit(&db_pool, user_id, &category, category_id, expected_version).await {
+        Ok(category) => Ok(HttpResponse::Ok()
+            .header(http::header::ETAG, etag(category.version))
+            .json(category)),
+        Err(Error::NotFound) if expected_version.is_some() => {
+            // the client's copy is stale, give them the current one
+            let current = db::get(&db_pool, user_id, category_id).await?;
+            Ok(HttpResponse::Conflict()
+                .header(http::header::ETAG, etag(current.version))
+                .json(current))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn move_to(
+    destination: Json<interop::MoveCategory>,
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!(
+        "move category {:?} to {:?}",
+        params.id, destination.parent_id
+    );
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
 
-    let category = db::edit(&db_pool, user_id, &category, category_id).await?;
+    let category = db::move_to(&db_pool, user_id, params.id, destination.parent_id).await?;
 
     Ok(HttpResponse::Ok().json(category))
 }
 
+pub async fn get_notes(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    query: Query<interop::CategoryNotesQuery>,
+    page: Query<PageParams>,
+    filter: Query<TagFilter>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get notes of category {:?}", params.id);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+    let include_descendants = query.descendants.unwrap_or(false);
+
+    let triaged_notes = db_notes::triaged_in_category(
+        &db_pool,
+        user_id,
+        params.id,
+        include_descendants,
+        &page,
+        filter.tag,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(triaged_notes))
+}
+
 pub async fn delete(
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    options: Query<interop::DeleteCategoryQuery>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("delete");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
 
-    db::delete(&db_pool, user_id, params.id).await?;
+    db::delete(&db_pool, user_id, params.id, &options).await?;
 
     Ok(HttpResponse::Ok().json(true))
 }
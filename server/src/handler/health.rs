@@ -0,0 +1,105 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// monitoring endpoints, served outside of /api and without a session so that
+// a local prometheus or a load balancer can reach them
+//
+use crate::db::health as db;
+use crate::db::notes as db_notes;
+use crate::metrics::{self, Metrics};
+use crate::ServerConfig;
+use actix_web::http::header;
+use actix_web::web::Data;
+use actix_web::{HttpRequest, HttpResponse};
+use deadpool_postgres::Pool;
+use tracing::warn;
+
+// the process is up and serving requests
+//
+pub async fn healthz() -> HttpResponse {
+    HttpResponse::Ok().content_type("text/plain").body("ok")
+}
+
+// the database can be used
+//
+pub async fn readyz(db_pool: Data<Pool>) -> HttpResponse {
+    match db::ping(&db_pool).await {
+        Ok(()) => HttpResponse::Ok().content_type("text/plain").body("ready"),
+        Err(e) => {
+            warn!("not ready: {}", e);
+            HttpResponse::ServiceUnavailable()
+                .content_type("text/plain")
+                .body("not ready")
+        }
+    }
+}
+
+// the metrics include figures across every user, so a scrape has to present
+// the configured metrics_token
+//
+pub async fn metrics(
+    db_pool: Data<Pool>,
+    request_metrics: Data<Metrics>,
+    server_config: Data<ServerConfig>,
+    req: HttpRequest,
+) -> HttpResponse {
+    let authorization = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok());
+    if !metrics::authorised(authorization, &server_config.metrics_token) {
+        return HttpResponse::NotFound().finish();
+    }
+
+    let mut out = String::new();
+    request_metrics.render(&mut out);
+
+    let status = db_pool.status();
+    metrics::gauge(
+        &mut out,
+        "memo_db_pool_connections",
+        "connections held by the database pool",
+        status.size as i64,
+    );
+    metrics::gauge(
+        &mut out,
+        "memo_db_pool_available",
+        "idle connections in the database pool, negative when requests are waiting for one",
+        status.available as i64,
+    );
+
+    // a scrape still succeeds without the database, the note counts are
+    // just missing from it
+    match db_notes::counts(&db_pool).await {
+        Ok(counts) => metrics::labelled_gauge(
+            &mut out,
+            "memo_notes",
+            "notes of every user by state",
+            "state",
+            &[
+                ("inbox", counts.inbox),
+                ("triaged", counts.triaged),
+                ("binned", counts.binned),
+            ],
+        ),
+        Err(e) => warn!("note counts unavailable: {}", e),
+    }
+
+    HttpResponse::Ok()
+        .content_type(metrics::CONTENT_TYPE)
+        .body(out)
+}
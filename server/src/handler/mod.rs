@@ -15,8 +15,75 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+pub mod admin;
 pub mod bin;
 pub mod categories;
+pub mod health;
 pub mod notes;
+pub mod revisions;
+pub mod rules;
+pub mod tags;
+pub mod totp;
 pub mod triaged;
 pub mod users;
+
+use crate::error::{Error, Result};
+use actix_web::http::header;
+use actix_web::HttpRequest;
+use rand::{thread_rng, RngCore};
+
+// the version of a resource that the client expects to be modifying, taken
+// from the If-Match header if there is one, otherwise from the payload
+//
+// If-Match may hold a comma separated list of entity tags, only a list
+// naming a single version can be checked against the database
+//
+pub fn expected_version(req: &HttpRequest, payload_version: Option<i32>) -> Result<Option<i32>> {
+    match req.headers().get(header::IF_MATCH) {
+        Some(value) => {
+            let value = value.to_str().map_err(|_| Error::InvalidResource)?;
+            parse_if_match(value)
+        }
+        None => Ok(payload_version),
+    }
+}
+
+fn parse_if_match(value: &str) -> Result<Option<i32>> {
+    let tags: Vec<&str> = value
+        .split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .collect();
+
+    if tags == ["*"] {
+        return Ok(None);
+    }
+
+    let mut versions = vec![];
+    for tag in tags {
+        let tag = tag.trim_start_matches("W/").trim_matches('"');
+        versions.push(tag.parse::<i32>()?);
+    }
+    versions.sort_unstable();
+    versions.dedup();
+
+    match versions.as_slice() {
+        [version] => Ok(Some(*version)),
+        _ => Err(Error::InvalidRequest(String::from(
+            "If-Match has to name exactly one version",
+        ))),
+    }
+}
+
+pub fn etag(version: i32) -> String {
+    format!("\"{}\"", version)
+}
+
+// hex encoded random bytes, used for invite codes and reset tokens
+//
+pub fn random_token(num_bytes: usize) -> String {
+    let mut bytes = vec![0; num_bytes];
+    thread_rng().fill_bytes(&mut bytes);
+
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
@@ -16,14 +16,18 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 use crate::db::notes as db;
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::handler::{etag, expected_version};
 use crate::interop::categories as interop_categories;
 use crate::interop::notes as interop;
-use crate::interop::IdParam;
+use crate::interop::tags::TagFilter;
+use crate::interop::{IdParam, PageParams};
 use crate::session;
+use crate::validation::Validate;
+use crate::ServerConfig;
 use actix_multipart::Multipart;
-use actix_web::web::{Data, Json, Path};
-use actix_web::{http, HttpResponse};
+use actix_web::web::{Data, Json, Path, Query};
+use actix_web::{http, HttpRequest, HttpResponse};
 use chrono::{DateTime, Utc};
 use deadpool_postgres::Pool;
 use futures::{StreamExt, TryStreamExt};
@@ -34,16 +38,19 @@ use tracing::info;
 pub async fn bookmark(
     mut payload: Multipart,
     db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("bookmark");
 
     let now: DateTime<Utc> = Utc::now();
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let mut proto_note = interop::ProtoNote {
         title: now.format("%F %T").to_string(),
         content: "".to_string(),
+        version: None,
     };
 
     // iterate over multipart stream
@@ -64,7 +71,9 @@ pub async fn bookmark(
         }
     }
 
-    let _note = db::create(&db_pool, user_id, &proto_note).await?;
+    proto_note.validate(&server_config.limits)?;
+
+    let _note = db::create(&db_pool, user_id, &proto_note, true).await?;
 
     Ok(redirect_to("/"))
 }
@@ -78,39 +87,86 @@ fn redirect_to(location: &str) -> HttpResponse {
 pub async fn create(
     note: Json<interop::ProtoNote>,
     db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("create");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let note = note.into_inner();
+    note.validate(&server_config.limits)?;
 
     info!("{:?}", &note);
 
-    let note = db::create(&db_pool, user_id, &note).await?;
+    let note = db::create(&db_pool, user_id, &note, false).await?;
 
     Ok(HttpResponse::Ok().json(note))
 }
 
-pub async fn get_all(db_pool: Data<Pool>, session: actix_session::Session) -> Result<HttpResponse> {
+pub async fn get_all(
+    db_pool: Data<Pool>,
+    page: Query<PageParams>,
+    filter: Query<TagFilter>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
     info!("get_all");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
 
-    let notes = db::all_non_triaged(&db_pool, user_id).await?;
+    let notes = db::all_non_triaged(&db_pool, user_id, &page, filter.tag).await?;
 
     Ok(HttpResponse::Ok().json(notes))
 }
 
+pub async fn search(
+    db_pool: Data<Pool>,
+    query: Query<interop::SearchQuery>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("search {:?}", &query);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+    let query = query.into_inner();
+
+    if query.q.trim().is_empty() {
+        let empty: Vec<interop::SearchResult> = vec![];
+        return Ok(HttpResponse::Ok().json(empty));
+    }
+
+    let results = db::search(&db_pool, user_id, &query).await?;
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
+pub async fn bulk(
+    request: Json<interop::BulkRequest>,
+    db_pool: Data<Pool>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("bulk {:?}", &request);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+    let request = request.into_inner();
+
+    let results = db::bulk(&db_pool, user_id, &request).await?;
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
 pub async fn triage(
     category: Json<interop_categories::Category>,
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("triage note {:?}", params.id);
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let note_id = params.id;
     let category = category.into_inner();
 
@@ -122,11 +178,12 @@ pub async fn triage(
 pub async fn bin(
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("bin note {:?}", params.id);
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let note_id = params.id;
 
     let binned_note = db::bin(&db_pool, user_id, note_id).await?;
@@ -137,31 +194,48 @@ pub async fn bin(
 pub async fn get(
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("get note {:?}", params.id);
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let note_id = params.id;
 
     let note = db::get(&db_pool, user_id, note_id).await?;
 
-    Ok(HttpResponse::Ok().json(note))
+    Ok(HttpResponse::Ok()
+        .header(http::header::ETAG, etag(note.version))
+        .json(note))
 }
 
 pub async fn edit(
     note: Json<interop::ProtoNote>,
     db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("edit");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let note_id = params.id;
     let note = note.into_inner();
-
-    let note = db::edit(&db_pool, user_id, &note, note_id).await?;
-
-    Ok(HttpResponse::Ok().json(note))
+    note.validate(&server_config.limits)?;
+    let expected_version = expected_version(&req, note.version)?;
+
+    match db::edit(&db_pool, user_id, &note, note_id, expected_version).await {
+        Ok(note) => Ok(HttpResponse::Ok()
+            .header(http::header::ETAG, etag(note.version))
+            .json(note)),
+        Err(Error::NotFound) if expected_version.is_some() => {
+            // the client's copy is stale, give them the current one
+            let current = db::get(&db_pool, user_id, note_id).await?;
+            Ok(HttpResponse::Conflict()
+                .header(http::header::ETAG, etag(current.version))
+                .json(current))
+        }
+        Err(e) => Err(e),
+    }
 }
@@ -0,0 +1,105 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::db::notes as db_notes;
+use crate::db::revisions as db;
+use crate::diff;
+use crate::error::Result;
+use crate::interop::revisions as interop;
+use crate::interop::IdParam;
+use crate::session;
+use actix_web::web::{Data, Path, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use deadpool_postgres::Pool;
+
+#[allow(unused_imports)]
+use tracing::info;
+
+pub async fn get_all(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get revisions of note {:?}", params.id);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+    let note_id = params.id;
+
+    let revisions = db::all(&db_pool, user_id, note_id).await?;
+
+    Ok(HttpResponse::Ok().json(revisions))
+}
+
+pub async fn get(
+    db_pool: Data<Pool>,
+    params: Path<interop::RevisionParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get revision {:?} of note {:?}", params.rev, params.id);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    let revision = db::get(&db_pool, user_id, params.id, params.rev).await?;
+
+    Ok(HttpResponse::Ok().json(revision))
+}
+
+pub async fn diff(
+    db_pool: Data<Pool>,
+    params: Path<interop::RevisionParam>,
+    query: Query<interop::DiffQuery>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("diff revision {:?} of note {:?}", params.rev, params.id);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    let from = db::get(&db_pool, user_id, params.id, params.rev).await?;
+    let (to_title, to_content) = if let Some(against) = query.against {
+        let to = db::get(&db_pool, user_id, params.id, against).await?;
+        (to.title, to.content)
+    } else {
+        let to = db_notes::get(&db_pool, user_id, params.id).await?;
+        (to.title, to.content)
+    };
+
+    let revision_diff = interop::RevisionDiff {
+        from: from.revision,
+        to: query.against,
+        title: diff::lines(&from.title, &to_title),
+        content: diff::lines(&from.content, &to_content),
+    };
+
+    Ok(HttpResponse::Ok().json(revision_diff))
+}
+
+pub async fn restore(
+    db_pool: Data<Pool>,
+    params: Path<interop::RevisionParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("restore revision {:?} of note {:?}", params.rev, params.id);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    let note = db_notes::restore_revision(&db_pool, user_id, params.id, params.rev).await?;
+
+    Ok(HttpResponse::Ok().json(note))
+}
@@ -0,0 +1,133 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::db::rules as db;
+use crate::error::Result;
+use crate::interop::rules as interop;
+use crate::interop::IdParam;
+use crate::session;
+use crate::validation::Validate;
+use crate::ServerConfig;
+use actix_web::web::{Data, Json, Path, Query};
+use actix_web::{HttpRequest, HttpResponse};
+use deadpool_postgres::Pool;
+
+#[allow(unused_imports)]
+use tracing::info;
+
+pub async fn create(
+    rule: Json<interop::ProtoRule>,
+    db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("create");
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+    let rule = rule.into_inner();
+    rule.validate(&server_config.limits)?;
+
+    info!("{:?}", &rule);
+
+    let rule = db::create(&db_pool, user_id, &rule).await?;
+
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+pub async fn get_all(
+    db_pool: Data<Pool>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get_all");
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    let rules = db::all(&db_pool, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(rules))
+}
+
+pub async fn get(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get rule {:?}", params.id);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    let rule = db::get(&db_pool, user_id, params.id).await?;
+
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+pub async fn edit(
+    rule: Json<interop::ProtoRule>,
+    db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    params: Path<IdParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("edit");
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+    let rule = rule.into_inner();
+    rule.validate(&server_config.limits)?;
+
+    let rule = db::edit(&db_pool, user_id, &rule, params.id).await?;
+
+    Ok(HttpResponse::Ok().json(rule))
+}
+
+pub async fn delete(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("delete");
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    db::delete(&db_pool, user_id, params.id).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+// returns the notes that were (or with dry_run, would be) moved out of the inbox
+//
+pub async fn apply(
+    db_pool: Data<Pool>,
+    query: Query<interop::ApplyQuery>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("apply (dry_run: {})", query.dry_run);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    let moved = if query.dry_run {
+        db::matches(&db_pool, user_id).await?
+    } else {
+        db::apply(&db_pool, user_id).await?
+    };
+
+    Ok(HttpResponse::Ok().json(moved))
+}
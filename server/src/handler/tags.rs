@@ -0,0 +1,153 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::db::notes as db_notes;
+use crate::db::tags as db;
+use crate::error::Result;
+use crate::interop::tags as interop;
+use crate::interop::IdParam;
+use crate::session;
+use crate::validation::Validate;
+use crate::ServerConfig;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
+use deadpool_postgres::Pool;
+
+#[allow(unused_imports)]
+use tracing::info;
+
+pub async fn create(
+    tag: Json<interop::ProtoTag>,
+    db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("create");
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+    let tag = tag.into_inner();
+    tag.validate(&server_config.limits)?;
+
+    info!("{:?}", &tag);
+
+    let tag = db::create(&db_pool, user_id, &tag).await?;
+
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+pub async fn get_all(
+    db_pool: Data<Pool>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get_all");
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    let tags = db::all(&db_pool, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(tags))
+}
+
+pub async fn get(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get tag {:?}", params.id);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+    let tag_id = params.id;
+
+    let tag = db::get(&db_pool, user_id, tag_id).await?;
+
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+pub async fn edit(
+    tag: Json<interop::ProtoTag>,
+    db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    params: Path<IdParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("edit");
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+    let tag_id = params.id;
+    let tag = tag.into_inner();
+    tag.validate(&server_config.limits)?;
+
+    let tag = db::edit(&db_pool, user_id, &tag, tag_id).await?;
+
+    Ok(HttpResponse::Ok().json(tag))
+}
+
+pub async fn delete(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("delete");
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    db::delete(&db_pool, user_id, params.id).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+// tags can be added to notes in any state (inbox, triaged or binned)
+//
+pub async fn add_to_note(
+    db_pool: Data<Pool>,
+    params: Path<interop::NoteTagParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("add tag {:?} to note {:?}", params.tag_id, params.id);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    // make sure that the tag exists before adding it
+    db::get(&db_pool, user_id, params.tag_id).await?;
+    db::add_to_note(&db_pool, user_id, params.id, params.tag_id).await?;
+
+    let note = db_notes::get(&db_pool, user_id, params.id).await?;
+
+    Ok(HttpResponse::Ok().json(note))
+}
+
+pub async fn remove_from_note(
+    db_pool: Data<Pool>,
+    params: Path<interop::NoteTagParam>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("remove tag {:?} from note {:?}", params.tag_id, params.id);
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    db::remove_from_note(&db_pool, user_id, params.id, params.tag_id).await?;
+
+    let note = db_notes::get(&db_pool, user_id, params.id).await?;
+
+    Ok(HttpResponse::Ok().json(note))
+}
@@ -0,0 +1,182 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::db::totp as db;
+use crate::db::users;
+use crate::error::{Error, Result};
+use crate::handler::random_token;
+use crate::handler::users::verify_encoded;
+use crate::interop::totp as interop;
+use crate::session;
+use crate::throttle::LoginThrottle;
+use crate::totp;
+use actix_web::web::{Data, Json};
+use actix_web::{HttpRequest, HttpResponse};
+use deadpool_postgres::Pool;
+use std::time::Instant;
+
+#[allow(unused_imports)]
+use tracing::info;
+
+const RECOVERY_CODE_COUNT: usize = 10;
+
+// recovery codes are shown as lowercase hex but people will type them in
+// however they like
+//
+fn normalise_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .collect::<String>()
+        .to_lowercase()
+}
+
+// requires the password, like disabling, so that a session on its own
+// can't be used to take over the second factor
+//
+pub async fn enrol(
+    enrol: Json<interop::EnrolTotp>,
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("enrol");
+
+    let user_id = session::current(&session, &db_pool).await?.user_id;
+
+    let password = users::password(&db_pool, user_id).await?;
+    if !verify_encoded(&password, enrol.password.as_bytes())? {
+        return Err(Error::Authenticating);
+    }
+
+    let user = users::get(&db_pool, user_id).await?;
+
+    let secret = totp::generate_secret();
+    let recovery_codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| random_token(5)).collect();
+
+    db::enrol(&db_pool, user_id, &secret, &recovery_codes).await?;
+
+    Ok(HttpResponse::Ok().json(interop::Enrolment {
+        otpauth_uri: totp::otpauth_uri(&secret, &user.email),
+        secret: totp::base32(&secret),
+        recovery_codes,
+    }))
+}
+
+// confirms that the authenticator app has the secret, only then is 2FA
+// required when logging in
+//
+pub async fn verify_enrolment(
+    code: Json<interop::TotpCode>,
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("verify_enrolment");
+
+    let user_id = session::current(&session, &db_pool).await?.user_id;
+
+    let secret = match db::get(&db_pool, user_id).await? {
+        (Some(secret), false) => secret,
+        _ => return Err(Error::InvalidResource),
+    };
+
+    let step = totp::verify(&secret, &code.code, chrono::Utc::now().timestamp())
+        .ok_or(Error::InvalidTotpCode)?;
+    db::enable(&db_pool, user_id, step).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+pub async fn disable(
+    disable: Json<interop::DisableTotp>,
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("disable");
+
+    let user_id = session::current(&session, &db_pool).await?.user_id;
+
+    let password = users::password(&db_pool, user_id).await?;
+    if !verify_encoded(&password, disable.password.as_bytes())? {
+        return Err(Error::Authenticating);
+    }
+
+    db::disable(&db_pool, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+// the second step of logging in, following a POST to /api/auth that
+// accepted the password
+//
+pub async fn login(
+    factor: Json<interop::SecondFactor>,
+    db_pool: Data<Pool>,
+    login_throttle: Data<LoginThrottle>,
+    session: actix_session::Session,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    info!("login (second factor)");
+
+    let pending = session::pending(&session)?;
+    let ip_address = session::client_ip(&req);
+
+    if let Some(retry_after) =
+        login_throttle.check(&pending.email, ip_address.as_deref(), Instant::now())
+    {
+        users::record_failed_login(&db_pool, &pending.email, ip_address.as_deref(), true).await?;
+        return Err(Error::TooManyAttempts(retry_after.as_secs() + 1));
+    }
+
+    let accepted = if let Some(code) = &factor.code {
+        let secret = match db::get(&db_pool, pending.user_id).await? {
+            (Some(secret), true) => secret,
+            _ => return Err(Error::Authenticating),
+        };
+        match totp::verify(&secret, code, chrono::Utc::now().timestamp()) {
+            // a code can only be used once
+            Some(step) => match db::use_step(&db_pool, pending.user_id, step).await {
+                Ok(()) => true,
+                Err(Error::NotFound) => false,
+                Err(e) => return Err(e),
+            },
+            None => false,
+        }
+    } else if let Some(code) = &factor.recovery_code {
+        let code = normalise_recovery_code(code);
+        match db::use_recovery_code(&db_pool, pending.user_id, &code).await {
+            Ok(()) => true,
+            Err(Error::NotFound) => false,
+            Err(e) => return Err(e),
+        }
+    } else {
+        false
+    };
+
+    if accepted {
+        login_throttle.record_success(&pending.email);
+        session::start(&session, &db_pool, &req, pending.user_id).await?;
+
+        let user = users::get(&db_pool, pending.user_id).await?;
+
+        info!("login accepted!!");
+        Ok(HttpResponse::Ok().json(user))
+    } else {
+        info!("second factor denied");
+        login_throttle.record_failure(&pending.email, ip_address.as_deref(), Instant::now());
+        users::record_failed_login(&db_pool, &pending.email, ip_address.as_deref(), false).await?;
+        Err(Error::Authenticating)
+    }
+}
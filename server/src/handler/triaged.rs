@@ -17,21 +17,29 @@
 
 use crate::db::notes as db;
 use crate::error::Result;
-use crate::interop::IdParam;
+use crate::handler::etag;
+use crate::interop::tags::TagFilter;
+use crate::interop::{IdParam, PageParams};
 use crate::session;
-use actix_web::web::{Data, Path};
-use actix_web::HttpResponse;
+use actix_web::web::{Data, Path, Query};
+use actix_web::{http, HttpRequest, HttpResponse};
 use deadpool_postgres::Pool;
 
 #[allow(unused_imports)]
 use tracing::info;
 
-pub async fn get_all(db_pool: Data<Pool>, session: actix_session::Session) -> Result<HttpResponse> {
+pub async fn get_all(
+    db_pool: Data<Pool>,
+    page: Query<PageParams>,
+    filter: Query<TagFilter>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
     info!("get_all");
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
 
-    let triaged_notes = db::triaged_all(&db_pool, user_id).await?;
+    let triaged_notes = db::triaged_all(&db_pool, user_id, &page, filter.tag).await?;
 
     Ok(HttpResponse::Ok().json(triaged_notes))
 }
@@ -39,26 +47,30 @@ pub async fn get_all(db_pool: Data<Pool>, session: actix_session::Session) -> Re
 pub async fn get(
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("get note {:?}", params.id);
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let note_id = params.id;
 
     let triaged_note = db::triaged_get(&db_pool, user_id, note_id).await?;
 
-    Ok(HttpResponse::Ok().json(triaged_note))
+    Ok(HttpResponse::Ok()
+        .header(http::header::ETAG, etag(triaged_note.version))
+        .json(triaged_note))
 }
 
 pub async fn untriage(
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("untriage note {:?}", params.id);
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let note_id = params.id;
 
     let untriaged_note = db::untriage(&db_pool, user_id, note_id).await?;
@@ -69,11 +81,12 @@ pub async fn untriage(
 pub async fn bin(
     db_pool: Data<Pool>,
     params: Path<IdParam>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("bin triaged note {:?}", params.id);
 
-    let user_id = session::user_id(&session)?;
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
     let note_id = params.id;
 
     let binned_note = db::bin(&db_pool, user_id, note_id).await?;
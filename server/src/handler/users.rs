@@ -15,60 +15,116 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::db::export as db_export;
 use crate::db::users as db;
+use crate::db::{sessions, tokens, totp};
 use crate::error::{Error, Result};
+use crate::export;
+use crate::handler::random_token;
+use crate::interop::export::AccountDeletion;
+use crate::interop::tokens::{NewToken, ProtoToken};
+use crate::interop::totp::TwoFactorRequired;
 use crate::interop::users as interop;
+use crate::interop::IdParam;
+use crate::mail::{Mail, Mailer};
 use crate::session;
-use actix_web::web::{Data, Json};
-use actix_web::HttpResponse;
+use crate::throttle::{LoginThrottle, ResetThrottle};
+use crate::validation::Validate;
+use crate::{RegistrationMode, ServerConfig};
+use actix_web::http::header;
+use actix_web::web::{Data, Json, Path};
+use actix_web::{HttpRequest, HttpResponse};
 use deadpool_postgres::Pool;
 use rand::{thread_rng, RngCore};
-use std::env;
+use std::time::Instant;
 
 #[allow(unused_imports)]
 use tracing::info;
 
+const PASSWORD_RESET_MINUTES: i32 = 60;
+
+// an unknown email is checked against this so that it takes as long to
+// reject as a wrong password (same argon2 parameters as hash_password)
+//
+const UNKNOWN_USER_HASH: &str =
+    "$argon2i$v=19$m=4096,t=3,p=1$bWVtby1kdW1teS1zYWx0IQ$gXXcL8sTvf2KiJaGuFSpFdgBQKu+yCtWTmQ/ZkNSWTQ";
+
 pub async fn login(
     login: Json<interop::LoginCredentials>,
     db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    login_throttle: Data<LoginThrottle>,
     session: actix_session::Session,
+    req: HttpRequest,
 ) -> Result<HttpResponse> {
     info!("login");
     let login = login.into_inner();
+    login.validate(&server_config.limits)?;
+    let ip_address = session::client_ip(&req);
 
-    let (id, password, mut user) = db::login(&db_pool, &login).await?;
+    if let Some(retry_after) =
+        login_throttle.check(&login.email, ip_address.as_deref(), Instant::now())
+    {
+        info!("login locked out");
+        db::record_failed_login(&db_pool, &login.email, ip_address.as_deref(), true).await?;
+        // round up so that retrying after the given time succeeds
+        return Err(Error::TooManyAttempts(retry_after.as_secs() + 1));
+    }
 
-    // compare hashed password of matched_user with the given LoginCredentials
-    let is_valid_password = verify_encoded(&password, login.password.as_bytes())?;
-    if is_valid_password {
-        // save id to the session
-        session::save_user_id(&session, id)?;
+    let accepted = match db::login(&db_pool, &login).await {
+        // compare hashed password of matched_user with the given LoginCredentials
+        Ok((id, password, user)) => {
+            if verify_encoded(&password, login.password.as_bytes())? {
+                Some((id, user))
+            } else {
+                None
+            }
+        }
+        // an unknown email is treated the same as a wrong password
+        Err(Error::NotFound) => {
+            verify_encoded(UNKNOWN_USER_HASH, login.password.as_bytes())?;
+            None
+        }
+        Err(e) => return Err(e),
+    };
 
-        if id == 1 {
-            user.admin = Some(interop::Admin {
-                db_name: env::var("POSTGRES_DB")?,
-            })
+    if let Some((id, user)) = accepted {
+        // the account isn't counted as a success until the second factor has
+        // been given, otherwise knowing the password would allow unlimited
+        // attempts at guessing codes
+        let (_, totp_enabled) = totp::get(&db_pool, id).await?;
+        if totp_enabled {
+            info!("login awaiting second factor");
+            session::start_pending(&session, id, &login.email)?;
+            return Ok(HttpResponse::Ok().json(TwoFactorRequired {
+                two_factor_required: true,
+            }));
         }
 
+        login_throttle.record_success(&login.email);
+        session::start(&session, &db_pool, &req, id).await?;
+
         info!("login accepted!!");
         // send response
         Ok(HttpResponse::Ok().json(user))
     } else {
         info!("login denied");
+        login_throttle.record_failure(&login.email, ip_address.as_deref(), Instant::now());
+        db::record_failed_login(&db_pool, &login.email, ip_address.as_deref(), false).await?;
         session.clear();
         Err(Error::Authenticating)
     }
 }
 
-pub async fn logout(_db_pool: Data<Pool>, session: actix_session::Session) -> Result<HttpResponse> {
+pub async fn logout(db_pool: Data<Pool>, session: actix_session::Session) -> Result<HttpResponse> {
     info!("logout");
-    session.purge();
+    session::end(&session, &db_pool).await?;
 
     // todo: what to return when logging out???
     Ok(HttpResponse::Ok().json(true))
 }
 
-fn verify_encoded(encoded: &str, pwd: &[u8]) -> Result<bool> {
+pub(crate) fn verify_encoded(encoded: &str, pwd: &[u8]) -> Result<bool> {
     let res = argon2::verify_encoded(encoded, pwd)?;
 
     Ok(res)
@@ -77,15 +133,34 @@ fn verify_encoded(encoded: &str, pwd: &[u8]) -> Result<bool> {
 pub async fn create_user(
     registration: Json<interop::Registration>,
     db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
     session: actix_session::Session,
+    req: HttpRequest,
 ) -> ::std::result::Result<HttpResponse, actix_web::Error> {
     let registration = registration.into_inner();
+    registration.validate(&server_config.limits)?;
+
+    // invite codes are checked when the user is created
+    let permitted = match server_config.registration_mode {
+        RegistrationMode::Open => true,
+        RegistrationMode::MagicWord => {
+            registration.invite_code.is_some()
+                || registration.magic_word.as_deref()
+                    == Some(server_config.registration_magic_word.as_str())
+        }
+        RegistrationMode::InviteOnly => registration.invite_code.is_some(),
+        RegistrationMode::Closed => false,
+    };
+    if !permitted {
+        info!("registration denied");
+        return Err(Error::RegistrationDenied.into());
+    }
+
     let hash = hash_password(&registration.password)?;
 
     let (id, user) = db::create(&db_pool, &registration, &hash).await?;
 
-    // save id to the session
-    session::save_user_id(&session, id)?;
+    session::start(&session, &db_pool, &req, id).await?;
 
     // send response
     Ok(HttpResponse::Ok().json(user))
@@ -93,18 +168,13 @@ pub async fn create_user(
 
 pub async fn get_user(
     db_pool: Data<Pool>,
+    req: HttpRequest,
     session: actix_session::Session,
 ) -> Result<HttpResponse> {
     info!("get_user");
 
-    if let Ok(user_id) = session::user_id(&session) {
-        let mut user = db::get(&db_pool, user_id).await?;
-
-        if user_id == 1 {
-            user.admin = Some(interop::Admin {
-                db_name: env::var("POSTGRES_DB")?,
-            })
-        }
+    if let Ok(user_id) = session::user_id(&req, &session, &db_pool).await {
+        let user = db::get(&db_pool, user_id).await?;
 
         Ok(HttpResponse::Ok().json(user))
     } else {
@@ -112,6 +182,276 @@ pub async fn get_user(
     }
 }
 
+pub async fn get_bin_retention(
+    db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get_bin_retention");
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+
+    let retention = db::get_bin_retention(&db_pool, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(interop::BinRetentionSettings {
+        days: retention.days,
+        default_days: server_config.bin_retention_days,
+    }))
+}
+
+pub async fn set_bin_retention(
+    retention: Json<interop::BinRetention>,
+    db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    req: HttpRequest,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("set_bin_retention");
+
+    let user_id = session::user_id(&req, &session, &db_pool).await?;
+    let retention = retention.into_inner();
+
+    if let Some(days) = retention.days {
+        if days < 1 {
+            return Err(Error::InvalidResource);
+        }
+    }
+
+    let retention = db::set_bin_retention(&db_pool, user_id, &retention).await?;
+
+    Ok(HttpResponse::Ok().json(interop::BinRetentionSettings {
+        days: retention.days,
+        default_days: server_config.bin_retention_days,
+    }))
+}
+
+pub async fn change_password(
+    change: Json<interop::PasswordChange>,
+    db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+    session: actix_session::Session,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    info!("change_password");
+
+    let user_id = session::current(&session, &db_pool).await?.user_id;
+
+    let password = db::password(&db_pool, user_id).await?;
+    if !verify_encoded(&password, change.current_password.as_bytes())? {
+        info!("change_password denied");
+        return Err(Error::Authenticating);
+    }
+    change.validate(&server_config.limits)?;
+
+    let hash = hash_password(&change.new_password)?;
+    db::set_password(&db_pool, user_id, &hash, false).await?;
+
+    // changing the password ended every session, keep this one logged in
+    session::start(&session, &db_pool, &req, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+// always succeeds so that the response doesn't reveal which email addresses
+// have accounts
+//
+pub async fn request_password_reset(
+    request: Json<interop::PasswordResetRequest>,
+    db_pool: Data<Pool>,
+    mailer: Data<Mailer>,
+    reset_throttle: Data<ResetThrottle>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    info!("request_password_reset");
+
+    let ip_address = session::client_ip(&req);
+    if let Some(retry_after) =
+        reset_throttle.request(&request.email, ip_address.as_deref(), Instant::now())
+    {
+        return Err(Error::TooManyAttempts(retry_after.as_secs() + 1));
+    }
+
+    let token = random_token(32);
+    match db::create_password_reset(&db_pool, &request.email, &token, PASSWORD_RESET_MINUTES).await
+    {
+        Ok(()) => mailer.send(&Mail {
+            to: request.email.clone(),
+            subject: String::from("Memo password reset"),
+            body: format!(
+                "A password reset was requested for your Memo account.\r\n\r\n\
+                 Reset token: {}\r\n\r\n\
+                 The token expires in {} minutes and can only be used once. \
+                 If you didn't request a reset you can ignore this mail.",
+                token, PASSWORD_RESET_MINUTES
+            ),
+        })?,
+        Err(Error::NotFound) => info!("no account for password reset"),
+        Err(e) => return Err(e),
+    }
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+pub async fn confirm_password_reset(
+    confirmation: Json<interop::PasswordResetConfirmation>,
+    db_pool: Data<Pool>,
+    server_config: Data<ServerConfig>,
+) -> Result<HttpResponse> {
+    info!("confirm_password_reset");
+
+    confirmation.validate(&server_config.limits)?;
+
+    let hash = hash_password(&confirmation.password)?;
+    db::reset_password(&db_pool, &confirmation.token, &hash).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+pub async fn get_sessions(
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get_sessions");
+
+    let current = session::current(&session, &db_pool).await?;
+
+    let mut sessions = sessions::all(&db_pool, current.user_id).await?;
+    for s in sessions.iter_mut() {
+        s.current = s.id == current.id;
+    }
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
+
+pub async fn delete_session(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("delete_session {:?}", params.id);
+
+    let current = session::current(&session, &db_pool).await?;
+
+    if current.id == params.id {
+        session::end(&session, &db_pool).await?;
+    } else {
+        sessions::delete(&db_pool, current.user_id, params.id).await?;
+    }
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+// log out everywhere, including this session
+//
+pub async fn delete_all_sessions(
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("delete_all_sessions");
+
+    let user_id = session::current(&session, &db_pool).await?.user_id;
+
+    sessions::delete_all(&db_pool, user_id).await?;
+    session.purge();
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+pub async fn create_token(
+    token: Json<ProtoToken>,
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("create_token");
+
+    let user_id = session::current(&session, &db_pool).await?.user_id;
+
+    if token.name.trim().is_empty() {
+        return Err(Error::InvalidResource);
+    }
+
+    let secret = random_token(32);
+    let token = tokens::create(&db_pool, &secret, user_id, &token).await?;
+
+    Ok(HttpResponse::Ok().json(NewToken { token, secret }))
+}
+
+pub async fn get_tokens(
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("get_tokens");
+
+    let user_id = session::current(&session, &db_pool).await?.user_id;
+
+    let tokens = tokens::all(&db_pool, user_id).await?;
+
+    Ok(HttpResponse::Ok().json(tokens))
+}
+
+pub async fn delete_token(
+    db_pool: Data<Pool>,
+    params: Path<IdParam>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("delete_token {:?}", params.id);
+
+    let user_id = session::current(&session, &db_pool).await?.user_id;
+
+    tokens::delete(&db_pool, user_id, params.id).await?;
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
+// like deleting the account, this is only available to a logged in
+// session and not to api tokens
+//
+pub async fn export(db_pool: Data<Pool>, session: actix_session::Session) -> Result<HttpResponse> {
+    info!("export");
+
+    let user_id = session::current(&session, &db_pool).await?.user_id;
+
+    let export = db_export::all(&db_pool, user_id).await?;
+    let archive = export::archive(&export)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/x-tar")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"{}-{}.tar\"",
+                export::ROOT,
+                export.exported_at.format("%Y%m%d")
+            ),
+        )
+        .body(archive))
+}
+
+// permanently removes the user and everything they've created, the password
+// is required even though they're logged in
+//
+pub async fn delete_user(
+    deletion: Json<AccountDeletion>,
+    db_pool: Data<Pool>,
+    session: actix_session::Session,
+) -> Result<HttpResponse> {
+    info!("delete_user");
+
+    let user_id = session::current(&session, &db_pool).await?.user_id;
+
+    let password = db::password(&db_pool, user_id).await?;
+    if !verify_encoded(&password, deletion.password.as_bytes())? {
+        info!("delete_user denied");
+        return Err(Error::Authenticating);
+    }
+
+    db::delete(&db_pool, user_id).await?;
+    session.purge();
+
+    Ok(HttpResponse::Ok().json(true))
+}
+
 fn generate_random_salt() -> [u8; 16] {
     let mut salt = [0; 16];
     thread_rng().fill_bytes(&mut salt);
@@ -119,7 +459,7 @@ fn generate_random_salt() -> [u8; 16] {
     salt
 }
 
-fn hash_password(password: &str) -> Result<String> {
+pub(crate) fn hash_password(password: &str) -> Result<String> {
     let salt = generate_random_salt();
     let hash = argon2::hash_encoded(password.as_bytes(), &salt, &argon2::Config::default())?;
 
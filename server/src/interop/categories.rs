@@ -15,15 +15,148 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::error::Result;
 use crate::interop::Key;
+use crate::validation::{Checks, Limits, Validate};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, serde::Deserialize)]
 pub struct ProtoCategory {
     pub title: String,
+    pub version: Option<i32>,
+    // only used when creating a category, use MoveCategory to re-parent
+    pub parent_id: Option<Key>,
+}
+
+impl Validate for ProtoCategory {
+    fn validate(&self, limits: &Limits) -> Result<()> {
+        Checks::new()
+            .not_blank("title", &self.title)
+            .max_length("title", &self.title, limits.max_title_length)
+            .finish()
+    }
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct Category {
     pub id: Key,
     pub title: String,
+    #[serde(default)]
+    pub version: i32,
+    #[serde(default)]
+    pub parent_id: Option<Key>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct CategoryNode {
+    pub id: Key,
+    pub title: String,
+    pub version: i32,
+    pub parent_id: Option<Key>,
+    pub children: Vec<CategoryNode>,
+}
+
+impl CategoryNode {
+    // siblings keep the order they had in the given list
+    //
+    pub fn build_tree(categories: Vec<Category>) -> Vec<CategoryNode> {
+        let ids: HashSet<Key> = categories.iter().map(|c| c.id).collect();
+
+        let mut children: HashMap<Option<Key>, Vec<Category>> = HashMap::new();
+        for category in categories {
+            // anything with an unknown parent is treated as a root
+            let parent_id = category.parent_id.filter(|p| ids.contains(p));
+            children.entry(parent_id).or_default().push(category);
+        }
+
+        take_children(&mut children, None)
+    }
+}
+
+fn take_children(
+    children: &mut HashMap<Option<Key>, Vec<Category>>,
+    parent_id: Option<Key>,
+) -> Vec<CategoryNode> {
+    children
+        .remove(&parent_id)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|c| CategoryNode {
+            id: c.id,
+            title: c.title,
+            version: c.version,
+            parent_id: c.parent_id,
+            children: take_children(children, Some(c.id)),
+        })
+        .collect()
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct MoveCategory {
+    // None moves the category to the top level
+    pub parent_id: Option<Key>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct CategoryNotesQuery {
+    pub descendants: Option<bool>,
+}
+
+// what to do with the notes that are triaged into a category that's being
+// deleted, reassign_to takes precedence if both are given
+//
+#[derive(Debug, serde::Deserialize)]
+pub struct DeleteCategoryQuery {
+    pub reassign_to: Option<Key>,
+    #[serde(default)]
+    pub untriage: bool,
+}
+
+// the count includes notes that are in the bin
+//
+#[derive(Debug, serde::Serialize)]
+pub struct CategoryInUse {
+    pub affected_notes: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn category(id: Key, parent_id: Option<Key>) -> Category {
+        Category {
+            id,
+            title: format!("c{}", id),
+            version: 1,
+            parent_id,
+        }
+    }
+
+    #[test]
+    fn test_build_tree() {
+        let tree = CategoryNode::build_tree(vec![
+            category(1, None),
+            category(2, Some(1)),
+            category(3, Some(2)),
+            category(4, None),
+            category(5, Some(1)),
+        ]);
+
+        assert_eq!(tree.len(), 2);
+        assert_eq!(tree[0].id, 1);
+        assert_eq!(tree[1].id, 4);
+
+        let children: Vec<Key> = tree[0].children.iter().map(|c| c.id).collect();
+        assert_eq!(children, vec![2, 5]);
+        assert_eq!(tree[0].children[0].children[0].id, 3);
+    }
+
+    #[test]
+    fn test_build_tree_with_missing_parent() {
+        let tree = CategoryNode::build_tree(vec![category(2, Some(1)), category(3, Some(2))]);
+
+        assert_eq!(tree.len(), 1);
+        assert_eq!(tree[0].id, 2);
+        assert_eq!(tree[0].children[0].id, 3);
+    }
 }
@@ -0,0 +1,73 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::interop::categories::Category;
+use crate::interop::revisions::Revision;
+use crate::interop::rules::Rule;
+use crate::interop::tags::Tag;
+use crate::interop::Key;
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Export {
+    pub exported_at: chrono::DateTime<chrono::Utc>,
+    pub user: ExportedUser,
+    pub categories: Vec<Category>,
+    pub tags: Vec<Tag>,
+    pub rules: Vec<Rule>,
+    pub notes: Vec<ExportedNote>,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ExportedUser {
+    pub username: String,
+    pub email: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+}
+
+// every note regardless of whether it's in the inbox, triaged or binned
+//
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct ExportedNote {
+    pub id: Key,
+    pub title: String,
+    pub content: String,
+    pub category_id: Option<Key>,
+    pub tags: Vec<Key>,
+    pub bookmarked: bool,
+    pub version: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub triaged_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub revisions: Vec<Revision>,
+}
+
+// what was created by importing an export into an account
+//
+#[derive(Debug, Default)]
+pub struct ImportSummary {
+    pub categories: usize,
+    pub tags: usize,
+    pub rules: usize,
+    pub notes: usize,
+    pub revisions: usize,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct AccountDeletion {
+    pub password: String,
+}
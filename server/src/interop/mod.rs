@@ -16,7 +16,15 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 pub mod categories;
+pub mod errors;
+pub mod export;
 pub mod notes;
+pub mod revisions;
+pub mod rules;
+pub mod sessions;
+pub mod tags;
+pub mod tokens;
+pub mod totp;
 pub mod users;
 
 pub type Key = i64;
@@ -25,3 +33,46 @@ pub type Key = i64;
 pub struct IdParam {
     pub id: Key,
 }
+
+pub const DEFAULT_PAGE_LIMIT: i64 = 50;
+pub const MAX_PAGE_LIMIT: i64 = 200;
+
+// keyset pagination: `after` is the id of the last item on the previous page
+//
+#[derive(Debug, serde::Deserialize)]
+pub struct PageParams {
+    pub limit: Option<i64>,
+    pub after: Option<Key>,
+}
+
+impl PageParams {
+    pub fn limit(&self) -> i64 {
+        self.limit
+            .unwrap_or(DEFAULT_PAGE_LIMIT)
+            .max(1)
+            .min(MAX_PAGE_LIMIT)
+    }
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub next_cursor: Option<Key>,
+}
+
+impl<T> Page<T> {
+    // expects the query to have asked for one more row than the limit,
+    // the presence of that extra row is how we know there is another page
+    //
+    pub fn from_overfetch(mut items: Vec<T>, limit: i64, key: impl Fn(&T) -> Key) -> Page<T> {
+        let limit = limit as usize;
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(key)
+        } else {
+            None
+        };
+
+        Page { items, next_cursor }
+    }
+}
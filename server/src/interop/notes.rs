@@ -15,7 +15,10 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::error::Result;
 use crate::interop::Key;
+use crate::snippet::Snippet;
+use crate::validation::{Checks, Limits, Validate};
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct TriagedNote {
@@ -24,6 +27,8 @@ pub struct TriagedNote {
     pub content: String,
     pub triaged_at: chrono::DateTime<chrono::Utc>,
     pub category_id: Key,
+    pub version: i32,
+    pub tags: Vec<Key>,
 }
 
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
@@ -31,10 +36,107 @@ pub struct Note {
     pub id: Key,
     pub title: String,
     pub content: String,
+    pub version: i32,
+    pub tags: Vec<Key>,
+}
+
+// a newly created note, the triage rules may have already moved it out of
+// the inbox in which case triaged_at and category_id are set
+//
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct CreatedNote {
+    pub id: Key,
+    pub title: String,
+    pub content: String,
+    pub triaged_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub category_id: Option<Key>,
+    pub version: i32,
+    pub tags: Vec<Key>,
 }
 
 #[derive(Debug, serde::Deserialize)]
 pub struct ProtoNote {
     pub title: String,
     pub content: String,
+    // the version the client's copy was based on, edits are rejected if
+    // the note has since been changed (an If-Match header takes precedence)
+    pub version: Option<i32>,
+}
+
+impl Validate for ProtoNote {
+    fn validate(&self, limits: &Limits) -> Result<()> {
+        Checks::new()
+            .not_blank("title", &self.title)
+            .max_length("title", &self.title, limits.max_title_length)
+            .max_size("content", &self.content, limits.max_content_size)
+            .finish()
+    }
+}
+
+#[derive(Debug)]
+pub struct NoteCounts {
+    pub inbox: i64,
+    pub triaged: i64,
+    pub binned: i64,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NoteState {
+    Inbox,
+    Triaged,
+    Bin,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    // when not given the search covers every note that isn't in the bin
+    pub state: Option<NoteState>,
+    pub tag: Option<Key>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SearchResult {
+    pub id: Key,
+    pub title: String,
+    pub triaged_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub category_id: Option<Key>,
+    pub deleted_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub rank: f32,
+    pub title_snippet: Snippet,
+    pub content_snippet: Snippet,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum BulkAction {
+    Triage { category_id: Key },
+    Bin,
+    Unbin,
+    Untriage,
+    Delete,
+    AddTag { tag_id: Key },
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct BulkRequest {
+    pub ids: Vec<Key>,
+    #[serde(flatten)]
+    pub action: BulkAction,
+}
+
+#[derive(Debug, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BulkStatus {
+    Done,
+    // the note was already in the requested state
+    Unchanged,
+    NotFound,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct BulkResult {
+    pub id: Key,
+    pub status: BulkStatus,
 }
@@ -0,0 +1,53 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::diff;
+use crate::interop::Key;
+
+#[derive(serde::Deserialize)]
+pub struct RevisionParam {
+    pub id: Key,
+    pub rev: i32,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DiffQuery {
+    // the revision to compare against, the current note when not given
+    pub against: Option<i32>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RevisionSummary {
+    pub revision: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub title: String,
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Revision {
+    pub revision: i32,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub title: String,
+    pub content: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RevisionDiff {
+    pub from: i32,
+    pub to: Option<i32>,
+    pub title: Vec<diff::Line>,
+    pub content: Vec<diff::Line>,
+}
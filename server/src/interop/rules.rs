@@ -0,0 +1,86 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::error::Result;
+use crate::interop::Key;
+use crate::validation::{Checks, Limits, Validate};
+use postgres_types::{FromSql, ToSql};
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize, ToSql, FromSql)]
+#[serde(rename_all = "snake_case")]
+#[postgres(name = "triage_rule_kind")]
+pub enum RuleKind {
+    // pattern is a case-insensitive regular expression matched against the title
+    #[postgres(name = "title_regex")]
+    TitleRegex,
+    // pattern is a domain, matches notes containing a link to it or any of its subdomains
+    #[postgres(name = "url_domain")]
+    UrlDomain,
+    // matches notes created via the bookmark endpoint, pattern is ignored
+    #[postgres(name = "bookmark")]
+    Bookmark,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ProtoRule {
+    pub priority: i32,
+    pub kind: RuleKind,
+    #[serde(default)]
+    pub pattern: String,
+    pub category_id: Key,
+    pub enabled: Option<bool>,
+}
+
+// whether a title_regex compiles is left to the database, as that's what
+// matches it
+//
+impl Validate for ProtoRule {
+    fn validate(&self, limits: &Limits) -> Result<()> {
+        let checks = match self.kind {
+            RuleKind::Bookmark => Checks::new(),
+            RuleKind::TitleRegex | RuleKind::UrlDomain => {
+                Checks::new().not_blank("pattern", &self.pattern)
+            }
+        };
+        checks
+            .max_length("pattern", &self.pattern, limits.max_title_length)
+            .finish()
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Rule {
+    pub id: Key,
+    pub priority: i32,
+    pub kind: RuleKind,
+    pub pattern: String,
+    pub category_id: Key,
+    pub enabled: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ApplyQuery {
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct RuleMatch {
+    pub note_id: Key,
+    pub note_title: String,
+    pub rule_id: Key,
+    pub category_id: Key,
+}
@@ -0,0 +1,52 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::error::Result;
+use crate::interop::Key;
+use crate::validation::{Checks, Limits, Validate};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ProtoTag {
+    pub title: String,
+}
+
+impl Validate for ProtoTag {
+    fn validate(&self, limits: &Limits) -> Result<()> {
+        Checks::new()
+            .not_blank("title", &self.title)
+            .max_length("title", &self.title, limits.max_title_length)
+            .finish()
+    }
+}
+
+#[derive(Debug, serde::Deserialize, serde::Serialize)]
+pub struct Tag {
+    pub id: Key,
+    pub title: String,
+    // the number of notes (excluding binned notes) that have this tag
+    pub note_count: i64,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TagFilter {
+    pub tag: Option<Key>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct NoteTagParam {
+    pub id: Key,
+    pub tag_id: Key,
+}
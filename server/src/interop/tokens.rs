@@ -0,0 +1,59 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::interop::Key;
+use postgres_types::{FromSql, ToSql};
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize, ToSql, FromSql)]
+#[serde(rename_all = "snake_case")]
+#[postgres(name = "api_token_scope")]
+pub enum TokenScope {
+    // GET requests only
+    #[postgres(name = "read")]
+    Read,
+    // everything apart from managing the account, its sessions and tokens
+    #[postgres(name = "read_write")]
+    ReadWrite,
+    // only creating notes through the bookmark endpoint
+    #[postgres(name = "bookmark")]
+    Bookmark,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ProtoToken {
+    pub name: String,
+    pub scope: TokenScope,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct Token {
+    pub id: Key,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+// only returned when the token is created, after that the secret can't be
+// recovered
+//
+#[derive(Debug, serde::Serialize)]
+pub struct NewToken {
+    #[serde(flatten)]
+    pub token: Token,
+    pub secret: String,
+}
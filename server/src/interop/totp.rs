@@ -0,0 +1,54 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// returned once when enrolling, the recovery codes can't be shown again
+//
+#[derive(Debug, serde::Serialize)]
+pub struct Enrolment {
+    pub otpauth_uri: String,
+    pub secret: String,
+    pub recovery_codes: Vec<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct EnrolTotp {
+    pub password: String,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TotpCode {
+    pub code: String,
+}
+
+// the second step of logging in takes either a code from the authenticator
+// app or one of the recovery codes
+//
+#[derive(Debug, serde::Deserialize)]
+pub struct SecondFactor {
+    pub code: Option<String>,
+    pub recovery_code: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DisableTotp {
+    pub password: String,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct TwoFactorRequired {
+    pub two_factor_required: bool,
+}
@@ -15,27 +15,151 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::error::Result;
+use crate::interop::Key;
+use crate::validation::{Checks, Limits, Validate, MAX_PASSWORD_LENGTH, MAX_USERNAME_LENGTH};
+use postgres_types::{FromSql, ToSql};
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Deserialize, serde::Serialize, ToSql, FromSql)]
+#[serde(rename_all = "snake_case")]
+#[postgres(name = "user_role")]
+pub enum UserRole {
+    #[postgres(name = "user")]
+    User,
+    #[postgres(name = "admin")]
+    Admin,
+}
+
 #[derive(Debug, serde::Deserialize)]
 pub struct LoginCredentials {
     pub email: String,
     pub password: String,
 }
 
+// the password policy isn't applied here, it may have changed since the
+// account was created
+//
+impl Validate for LoginCredentials {
+    fn validate(&self, _limits: &Limits) -> Result<()> {
+        Checks::new()
+            .not_blank("email", &self.email)
+            .not_blank("password", &self.password)
+            .max_size("password", &self.password, MAX_PASSWORD_LENGTH)
+            .finish()
+    }
+}
+
 #[derive(serde::Deserialize)]
 pub struct Registration {
     pub username: String,
     pub email: String,
     pub password: String,
+    pub magic_word: Option<String>,
+    // an invite code can be used instead of the magic word
+    pub invite_code: Option<String>,
+}
+
+impl Validate for Registration {
+    fn validate(&self, limits: &Limits) -> Result<()> {
+        Checks::new()
+            .not_blank("username", &self.username)
+            .max_length("username", &self.username, MAX_USERNAME_LENGTH)
+            .email("email", &self.email)
+            .password("password", &self.password, limits)
+            .finish()
+    }
 }
 
 #[derive(serde::Serialize)]
 pub struct User {
     pub username: String,
     pub email: String,
-    pub admin: Option<Admin>,
+    pub role: UserRole,
+}
+
+// the number of days a note stays in the bin before being permanently
+// deleted, None means that the server's default is used
+//
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct BinRetention {
+    pub days: Option<i32>,
 }
 
 #[derive(serde::Serialize)]
-pub struct Admin {
-    pub db_name: String,
+pub struct BinRetentionSettings {
+    pub days: Option<i32>,
+    pub default_days: i32,
+}
+
+#[derive(serde::Deserialize)]
+pub struct ProtoInvite {
+    pub expires_in_hours: Option<i32>,
+}
+
+#[derive(serde::Serialize)]
+pub struct Invite {
+    pub id: Key,
+    pub code: String,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub used_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(serde::Serialize)]
+pub struct UserSummary {
+    pub id: Key,
+    pub username: String,
+    pub email: String,
+    pub role: UserRole,
+    pub disabled: bool,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub inbox_notes: i64,
+    pub triaged_notes: i64,
+    pub binned_notes: i64,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PasswordReset {
+    pub password: String,
+}
+
+impl Validate for PasswordReset {
+    fn validate(&self, limits: &Limits) -> Result<()> {
+        Checks::new()
+            .password("password", &self.password, limits)
+            .finish()
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PasswordChange {
+    pub current_password: String,
+    pub new_password: String,
+}
+
+impl Validate for PasswordChange {
+    fn validate(&self, limits: &Limits) -> Result<()> {
+        Checks::new()
+            .password("new_password", &self.new_password, limits)
+            .finish()
+    }
+}
+
+#[derive(serde::Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PasswordResetConfirmation {
+    pub token: String,
+    pub password: String,
+}
+
+impl Validate for PasswordResetConfirmation {
+    fn validate(&self, limits: &Limits) -> Result<()> {
+        Checks::new()
+            .not_blank("token", &self.token)
+            .password("password", &self.password, limits)
+            .finish()
+    }
 }
@@ -16,89 +16,166 @@
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
 mod api;
+mod archive;
+mod cli;
+mod config;
 mod db;
+mod diff;
 mod error;
+mod export;
 mod handler;
 mod interop;
+mod mail;
+mod metrics;
+mod migrations;
+mod purge;
 mod session;
+mod snippet;
+mod throttle;
+mod tls;
+mod totp;
+mod validation;
 
+pub use crate::cli::run;
+pub use crate::config::Config;
 pub use crate::error::{Error, Result};
 
+use crate::config::MailTransport;
 use actix_files as fs;
 use actix_session::CookieSession;
 use actix_web::cookie::SameSite;
+use actix_web::dev::Service;
 use actix_web::middleware::errhandlers::ErrorHandlers;
 use actix_web::{http, web, App, HttpServer};
 use dotenv;
-use std::env;
+use futures::future::FutureExt;
+use std::os::unix::fs::FileTypeExt;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio_postgres::NoTls;
+use tracing::info;
 use tracing::Level;
-use tracing::{error, info};
 use tracing_subscriber::FmtSubscriber;
 
-const SIGNING_KEY_SIZE: usize = 32;
+#[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegistrationMode {
+    // anyone can register
+    Open,
+    // registering requires either the magic word or an invite code
+    MagicWord,
+    // registering requires an invite code
+    InviteOnly,
+    // nobody can register
+    Closed,
+}
 
 pub struct ServerConfig {
+    pub registration_mode: RegistrationMode,
     pub registration_magic_word: String,
+    pub bin_retention_days: i32,
+    pub limits: validation::Limits,
+    pub trust_proxy_headers: bool,
+    pub metrics_token: String,
 }
 
-fn env_var_string(key: &str) -> Result<String> {
-    match env::var(key) {
-        Ok(r) => Ok(r),
-        Err(e) => {
-            error!("Error unable to get environment variable: {}", key);
-            Err(Error::Var(e))
-        }
+fn mail_transport(config: &Config) -> Result<Box<dyn mail::Transport>> {
+    match (config.mail_transport, &config.mail_dir) {
+        (MailTransport::Stdout, _) => Ok(Box::new(mail::StdoutTransport)),
+        (MailTransport::File, Some(dir)) => Ok(Box::new(mail::FileTransport::new(dir)?)),
+        (MailTransport::File, None) => Err(Error::Configuration(String::from(
+            "mail_dir is required when mail_transport is file",
+        ))),
     }
 }
 
-fn env_var_bool(key: &str) -> Result<bool> {
-    Ok(env_var_string(key)? == "true")
-}
-
-pub async fn start_server() -> Result<()> {
-    dotenv::dotenv().ok();
-
+fn init_tracing() {
     // a builder for `FmtSubscriber`.
     let subscriber = FmtSubscriber::builder()
         .with_max_level(Level::TRACE)
         .finish();
 
     tracing::subscriber::set_global_default(subscriber).expect("setting default subscriber failed");
+}
 
-    let port = env_var_string("PORT")?;
-    let www_path = env_var_string("WWW_PATH")?;
-    let registration_magic_word = env_var_string("REGISTRATION_MAGIC_WORD")?;
-    let postgres_db = env_var_string("POSTGRES_DB")?;
-    let postgres_host = env_var_string("POSTGRES_HOST")?;
-    let postgres_user = env_var_string("POSTGRES_USER")?;
-    let postgres_password = env_var_string("POSTGRES_PASSWORD")?;
-    let cookie_secure = env_var_bool("COOKIE_OVER_HTTPS_ONLY")?;
+fn create_pool(config: &Config) -> Result<deadpool_postgres::Pool> {
+    let mut pool = deadpool_postgres::PoolConfig::new(config.postgres_pool_size);
+    if config.postgres_pool_timeout_secs > 0 {
+        pool.timeouts.wait = Some(Duration::from_secs(config.postgres_pool_timeout_secs));
+    }
 
     let cfg = deadpool_postgres::Config {
-        user: Some(String::from(&postgres_user)),
-        password: Some(String::from(&postgres_password)),
-        dbname: Some(String::from(&postgres_db)),
-        host: Some(String::from(&postgres_host)),
+        user: Some(config.postgres_user.clone()),
+        password: Some(config.postgres_password.clone()).filter(|p| !p.is_empty()),
+        dbname: Some(config.postgres_db.clone()),
+        host: Some(config.postgres_host.clone()),
+        port: Some(config.postgres_port),
+        pool: Some(pool),
         ..Default::default()
     };
 
-    let session_signing_key = env::var("SESSION_SIGNING_KEY")?;
+    Ok(cfg.create_pool(NoTls)?)
+}
+
+pub async fn start_server() -> Result<()> {
+    dotenv::dotenv().ok();
+    init_tracing();
 
-    let pool: deadpool_postgres::Pool = cfg.create_pool(NoTls)?;
+    let config = Config::load()?;
+    info!("configuration:\n{}", config.redacted()?);
+
+    let signing_key = config.signing_key()?;
+    let mailer = web::Data::new(mail::Mailer::new(
+        config.mail_from.clone(),
+        mail_transport(&config)?,
+    ));
+    let login_throttle = web::Data::new(throttle::LoginThrottle::new());
+    let reset_throttle = web::Data::new(throttle::ResetThrottle::new());
+    let request_metrics = web::Data::new(metrics::Metrics::new());
+
+    let pool = create_pool(&config)?;
 
     // crash on startup if no database connection can be established
     let _ = pool.get().await?;
 
-    let server = HttpServer::new(move || {
-        let mut signing_key: &mut [u8] = &mut [0; SIGNING_KEY_SIZE];
-        read_signing_key(&mut signing_key, &session_signing_key);
-        // info!("signing key: {:?}", signing_key);
+    // refuse to run against a schema that's older than the code expects
+    if config.auto_migrate {
+        migrations::up(&pool).await?;
+    } else {
+        let pending = migrations::pending(&pool).await?;
+        if !pending.is_empty() {
+            return Err(Error::Configuration(format!(
+                "{} pending database migration(s), run `memo_server migrate up` or set AUTO_MIGRATE=true",
+                pending.len()
+            )));
+        }
+    }
+
+    actix_rt::spawn(purge::purge_periodically(
+        pool.clone(),
+        config.bin_retention_days,
+    ));
+
+    let tls = if let (Some(certificate), Some(private_key)) =
+        (&config.tls_certificate, &config.tls_private_key)
+    {
+        let resolver = Arc::new(tls::CertificateResolver::new(certificate, private_key)?);
+        actix_rt::spawn(tls::reload_on_hangup(resolver.clone()));
+        Some(tls::server_config(resolver))
+    } else {
+        None
+    };
+
+    let app_config = config.clone();
+
+    let mut server = HttpServer::new(move || {
+        let config = &app_config;
 
-        let session_store = CookieSession::private(signing_key)
-            .secure(cookie_secure)
+        let session_store = CookieSession::private(&signing_key)
+            .secure(config.secure_cookies())
             .same_site(SameSite::Strict)
-            .max_age(60 * 60 * 24 * 30); // 30 days
+            .max_age(60 * 60 * 24 * i64::from(session::IDLE_DAYS));
         let error_handlers = ErrorHandlers::new()
             .handler(
                 http::StatusCode::INTERNAL_SERVER_ERROR,
@@ -110,67 +187,79 @@ pub async fn start_server() -> Result<()> {
         App::new()
             .data(pool.clone())
             .data(ServerConfig {
-                registration_magic_word: registration_magic_word.clone(),
+                registration_mode: config.registration_mode,
+                registration_magic_word: config.registration_magic_word.clone(),
+                bin_retention_days: config.bin_retention_days,
+                limits: config.limits(),
+                trust_proxy_headers: config.trust_proxy_headers,
+                metrics_token: config.metrics_token.clone(),
             })
-            .data(web::JsonConfig::default().limit(1024 * 1024))
+            .app_data(mailer.clone())
+            .app_data(login_throttle.clone())
+            .app_data(reset_throttle.clone())
+            .app_data(request_metrics.clone())
+            .app_data(
+                web::JsonConfig::default()
+                    .limit(config.json_limit())
+                    .error_handler(api::json_error),
+            )
+            .app_data(web::QueryConfig::default().error_handler(api::query_error))
+            .app_data(web::PathConfig::default().error_handler(api::path_error))
             .wrap(session_store)
             .wrap(error_handlers)
-            .service(api::public_api("/api"))
-            .service(fs::Files::new("/nocache", &www_path))
-            .service(fs::Files::new("/", &www_path).index_file("index.html"))
-    })
-    .bind(format!("127.0.0.1:{}", port))?
-    .run();
-
-    info!("local server running on port: {}", port);
-
-    server.await?;
-
-    Ok(())
-}
+            // outermost so that the time spent in the other middleware is included
+            .wrap_fn({
+                let request_metrics = request_metrics.clone();
+                move |req, srv| {
+                    let request_metrics = request_metrics.clone();
+                    let start = Instant::now();
+                    srv.call(req).map(move |res| {
+                        if let Ok(res) = &res {
+                            request_metrics.record(res, start.elapsed());
+                        }
+                        res
+                    })
+                }
+            })
+            .route("/healthz", web::get().to(handler::health::healthz))
+            .route("/readyz", web::get().to(handler::health::readyz))
+            .route("/metrics", web::get().to(handler::health::metrics))
+            .service(api::public_api(api::MOUNT_POINT))
+            .service(fs::Files::new("/nocache", &config.www_path))
+            .service(fs::Files::new("/", &config.www_path).index_file("index.html"))
+    });
 
-fn read_signing_key(signing_key: &mut [u8], session_signing_key: &str) {
-    // check string against twice the SIGNING_KEY_SIZE since we
-    // need 2 characters to represent all byte values (00 -> ff)
-    //
-    if session_signing_key.len() != (SIGNING_KEY_SIZE * 2) {
-        error!(
-            "SESSION_SIGNING_KEY in .env has to be 32 bytes (currently: {})",
-            session_signing_key.len()
+    for address in config.socket_addresses()? {
+        server = match &tls {
+            Some(tls) => server.bind_rustls(address, tls.clone())?,
+            None => server.bind(address)?,
+        };
+        info!(
+            "server listening on {}://{}",
+            if tls.is_some() { "https" } else { "http" },
+            address
         );
     }
+    if let Some(path) = &config.unix_socket {
+        remove_stale_socket(path)?;
+        server = server.bind_uds(path)?;
+        info!("server listening on unix socket {}", path.display());
+    }
 
-    let mut b = session_signing_key.bytes();
-
-    for elem in signing_key.iter_mut().take(SIGNING_KEY_SIZE) {
-        let ascii_hex_0 = b.next().unwrap();
-        let ascii_hex_1 = b.next().unwrap();
-
-        let d0 = ascii_hex_digit_to_dec(ascii_hex_0);
-        let d1 = ascii_hex_digit_to_dec(ascii_hex_1);
+    server.run().await?;
 
-        *elem = (d0 * 16) + d1;
-    }
+    Ok(())
 }
 
-fn ascii_hex_digit_to_dec(ascii_hex: u8) -> u8 {
-    match ascii_hex {
-        48 => 0,   // asci 0
-        49 => 1,   // asci 1
-        50 => 2,   // asci 2
-        51 => 3,   // asci 3
-        52 => 4,   // asci 4
-        53 => 5,   // asci 5
-        54 => 6,   // asci 6
-        55 => 7,   // asci 7
-        56 => 8,   // asci 8
-        57 => 9,   // asci 9
-        97 => 10,  // ascii a
-        98 => 11,  // ascii b
-        99 => 12,  // ascii c
-        100 => 13, // ascii d
-        101 => 14, // ascii e
-        102 => 15, // ascii f
-        _ => 0,
+// a socket left behind by a previous run would stop the server from binding
+//
+fn remove_stale_socket(path: &Path) -> Result<()> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => Ok(std::fs::remove_file(path)?),
+        Ok(_) => Err(Error::Configuration(format!(
+            "unix_socket: {} exists and isn't a socket",
+            path.display()
+        ))),
+        Err(_) => Ok(()),
     }
 }
@@ -0,0 +1,100 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::error::Result;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+#[allow(unused_imports)]
+use tracing::info;
+
+pub struct Mail {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+// how outgoing mail is delivered, there's no SMTP transport yet so during
+// development the stdout or file transports can be used to read the mail
+//
+pub trait Transport: Send + Sync {
+    fn send(&self, from: &str, mail: &Mail) -> Result<()>;
+}
+
+pub struct Mailer {
+    from: String,
+    transport: Box<dyn Transport>,
+}
+
+impl Mailer {
+    pub fn new(from: String, transport: Box<dyn Transport>) -> Mailer {
+        Mailer { from, transport }
+    }
+
+    pub fn send(&self, mail: &Mail) -> Result<()> {
+        info!("sending mail to {}: {}", mail.to, mail.subject);
+        self.transport.send(&self.from, mail)
+    }
+}
+
+fn format_mail(from: &str, mail: &Mail) -> String {
+    format!(
+        "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {}\r\n\r\n{}\r\n",
+        from,
+        mail.to,
+        mail.subject,
+        chrono::Utc::now().to_rfc2822(),
+        mail.body
+    )
+}
+
+pub struct StdoutTransport;
+
+impl Transport for StdoutTransport {
+    fn send(&self, from: &str, mail: &Mail) -> Result<()> {
+        println!("{}", format_mail(from, mail));
+        Ok(())
+    }
+}
+
+// writes every mail into its own .eml file in the given directory
+//
+pub struct FileTransport {
+    dir: PathBuf,
+}
+
+impl FileTransport {
+    pub fn new(dir: &Path) -> Result<FileTransport> {
+        fs::create_dir_all(dir)?;
+        Ok(FileTransport {
+            dir: PathBuf::from(dir),
+        })
+    }
+}
+
+impl Transport for FileTransport {
+    fn send(&self, from: &str, mail: &Mail) -> Result<()> {
+        let now = chrono::Utc::now();
+        let filename = format!(
+            "{}-{:09}.eml",
+            now.format("%Y%m%d%H%M%S"),
+            now.timestamp_subsec_nanos()
+        );
+        fs::write(self.dir.join(filename), format_mail(from, mail))?;
+        Ok(())
+    }
+}
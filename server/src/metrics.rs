@@ -0,0 +1,357 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// request and error counts for the prometheus /metrics endpoint. they're
+// only kept in memory so they start from zero whenever the server restarts,
+// which prometheus treats as a counter reset
+//
+use crate::error::Error;
+use actix_web::dev::ServiceResponse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// the prometheus text exposition format
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+// upper bounds in seconds
+const LATENCY_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// requests that didn't match a route are counted together, otherwise anyone
+// could create an unbounded number of series by requesting random paths
+const UNMATCHED_ROUTE: &str = "unmatched";
+
+#[derive(Default)]
+struct Latencies {
+    // not cumulative, that's done when rendering
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Default)]
+struct Recorded {
+    // keyed by method, route and status
+    requests: BTreeMap<(String, String, u16), u64>,
+    // keyed by method and route
+    latencies: BTreeMap<(String, String), Latencies>,
+    // keyed by the Error variant
+    errors: BTreeMap<String, u64>,
+}
+
+#[derive(Default)]
+pub struct Metrics {
+    recorded: Mutex<Recorded>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Default::default()
+    }
+
+    pub fn record<B>(&self, res: &ServiceResponse<B>, elapsed: Duration) {
+        let request = res.request();
+        let route = request
+            .match_pattern()
+            .unwrap_or_else(|| String::from(UNMATCHED_ROUTE));
+        let error = res
+            .response()
+            .error()
+            .and_then(|e| e.as_error::<Error>())
+            .map(Error::variant);
+
+        self.observe(
+            request.method().as_str(),
+            &route,
+            res.status().as_u16(),
+            elapsed,
+            error.as_deref(),
+        );
+    }
+
+    fn observe(
+        &self,
+        method: &str,
+        route: &str,
+        status: u16,
+        elapsed: Duration,
+        error: Option<&str>,
+    ) {
+        let mut recorded = self.recorded.lock().unwrap();
+
+        *recorded
+            .requests
+            .entry((method.to_string(), route.to_string(), status))
+            .or_default() += 1;
+
+        let secs = elapsed.as_secs_f64();
+        let latencies = recorded
+            .latencies
+            .entry((method.to_string(), route.to_string()))
+            .or_default();
+        if let Some(i) = LATENCY_BUCKETS.iter().position(|&le| secs <= le) {
+            latencies.buckets[i] += 1;
+        }
+        latencies.sum += secs;
+        latencies.count += 1;
+
+        if let Some(variant) = error {
+            *recorded.errors.entry(variant.to_string()).or_default() += 1;
+        }
+    }
+
+    pub fn render(&self, out: &mut String) {
+        let recorded = self.recorded.lock().unwrap();
+
+        describe(
+            out,
+            "memo_http_requests_total",
+            "counter",
+            "HTTP requests by route and response status",
+        );
+        for ((method, route, status), count) in &recorded.requests {
+            writeln!(
+                out,
+                "memo_http_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+                method,
+                escape(route),
+                status,
+                count
+            )
+            .unwrap();
+        }
+
+        describe(
+            out,
+            "memo_http_request_duration_seconds",
+            "histogram",
+            "time taken to respond to HTTP requests",
+        );
+        for ((method, route), latencies) in &recorded.latencies {
+            let labels = format!("method=\"{}\",route=\"{}\"", method, escape(route));
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(latencies.buckets.iter()) {
+                cumulative += count;
+                writeln!(
+                    out,
+                    "memo_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                )
+                .unwrap();
+            }
+            writeln!(
+                out,
+                "memo_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, latencies.count
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "memo_http_request_duration_seconds_sum{{{}}} {}",
+                labels, latencies.sum
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "memo_http_request_duration_seconds_count{{{}}} {}",
+                labels, latencies.count
+            )
+            .unwrap();
+        }
+
+        describe(
+            out,
+            "memo_errors_total",
+            "counter",
+            "errors returned by request handlers",
+        );
+        for (variant, count) in &recorded.errors {
+            writeln!(
+                out,
+                "memo_errors_total{{variant=\"{}\"}} {}",
+                escape(variant),
+                count
+            )
+            .unwrap();
+        }
+    }
+}
+
+pub fn gauge(out: &mut String, name: &str, help: &str, value: i64) {
+    describe(out, name, "gauge", help);
+    writeln!(out, "{} {}", name, value).unwrap();
+}
+
+pub fn labelled_gauge(
+    out: &mut String,
+    name: &str,
+    help: &str,
+    label: &str,
+    values: &[(&str, i64)],
+) {
+    describe(out, name, "gauge", help);
+    for (label_value, value) in values {
+        writeln!(
+            out,
+            "{}{{{}=\"{}\"}} {}",
+            name,
+            label,
+            escape(label_value),
+            value
+        )
+        .unwrap();
+    }
+}
+
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+fn escape(label_value: &str) -> String {
+    label_value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// where a scrape comes from isn't considered, a reverse proxy on the same
+// machine makes every public request look local. without a configured token
+// the endpoint is off
+//
+pub fn authorised(authorization: Option<&str>, token: &str) -> bool {
+    if token.is_empty() {
+        return false;
+    }
+
+    match authorization.and_then(|value| value.strip_prefix("Bearer ")) {
+        Some(given) => constant_time_eq(given.trim().as_bytes(), token.as_bytes()),
+        None => false,
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: &str = "0123456789abcdef0123456789abcdef";
+
+    #[test]
+    fn test_authorised() {
+        let bearer = format!("Bearer {}", TOKEN);
+        assert!(authorised(Some(&bearer), TOKEN));
+
+        // a public request passed on by a reverse proxy arrives from a local
+        // address but doesn't have the token
+        assert!(!authorised(None, TOKEN));
+        assert!(!authorised(Some("Bearer guess"), TOKEN));
+        assert!(!authorised(Some(TOKEN), TOKEN));
+
+        // no token configured, nothing gets in
+        assert!(!authorised(Some("Bearer "), ""));
+        assert!(!authorised(None, ""));
+    }
+
+    fn rendered(metrics: &Metrics) -> String {
+        let mut out = String::new();
+        metrics.render(&mut out);
+        out
+    }
+
+    #[test]
+    fn test_requests() {
+        let metrics = Metrics::new();
+        let route = "/api/notes/{id}";
+        metrics.observe("GET", route, 200, Duration::from_millis(3), None);
+        metrics.observe("GET", route, 200, Duration::from_millis(30), None);
+        metrics.observe(
+            "GET",
+            route,
+            404,
+            Duration::from_millis(2),
+            Some("NotFound"),
+        );
+
+        let out = rendered(&metrics);
+        assert!(out.contains(
+            "memo_http_requests_total{method=\"GET\",route=\"/api/notes/{id}\",status=\"200\"} 2"
+        ));
+        assert!(out.contains(
+            "memo_http_requests_total{method=\"GET\",route=\"/api/notes/{id}\",status=\"404\"} 1"
+        ));
+        assert!(out.contains("memo_errors_total{variant=\"NotFound\"} 1"));
+    }
+
+    #[test]
+    fn test_histogram() {
+        let metrics = Metrics::new();
+        metrics.observe("POST", "/api/notes", 200, Duration::from_millis(3), None);
+        metrics.observe("POST", "/api/notes", 200, Duration::from_millis(30), None);
+        metrics.observe("POST", "/api/notes", 200, Duration::from_secs(60), None);
+
+        let out = rendered(&metrics);
+        let labels = "method=\"POST\",route=\"/api/notes\"";
+        // buckets are cumulative
+        assert!(out.contains(&format!(
+            "memo_http_request_duration_seconds_bucket{{{},le=\"0.005\"}} 1",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "memo_http_request_duration_seconds_bucket{{{},le=\"0.05\"}} 2",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "memo_http_request_duration_seconds_bucket{{{},le=\"10\"}} 2",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "memo_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 3",
+            labels
+        )));
+        assert!(out.contains(&format!(
+            "memo_http_request_duration_seconds_count{{{}}} 3",
+            labels
+        )));
+    }
+
+    #[test]
+    fn test_gauges() {
+        let mut out = String::new();
+        labelled_gauge(
+            &mut out,
+            "memo_notes",
+            "notes by state",
+            "state",
+            &[("inbox", 4), ("binned", 0)],
+        );
+        assert_eq!(
+            out,
+            "# HELP memo_notes notes by state\n\
+             # TYPE memo_notes gauge\n\
+             memo_notes{state=\"inbox\"} 4\n\
+             memo_notes{state=\"binned\"} 0\n"
+        );
+        assert_eq!(escape("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
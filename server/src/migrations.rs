@@ -0,0 +1,312 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::error::{Error, Result};
+use chrono::{DateTime, Utc};
+use deadpool_postgres::{Client, Pool, Transaction};
+use tokio_postgres::Row;
+use tracing::info;
+
+// every change to the schema ships as a new migration appended to this list,
+// the sql is embedded in the binary so that a deployed server can upgrade its
+// own database. versions have to be increasing and are never reused
+//
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "initial",
+        up: include_str!("../migrations/0001_initial.up.sql"),
+        down: include_str!("../migrations/0001_initial.down.sql"),
+    },
+    Migration {
+        version: 2,
+        name: "search_index",
+        up: include_str!("../migrations/0002_search_index.up.sql"),
+        down: include_str!("../migrations/0002_search_index.down.sql"),
+    },
+    Migration {
+        version: 3,
+        name: "note_revisions",
+        up: include_str!("../migrations/0003_note_revisions.up.sql"),
+        down: include_str!("../migrations/0003_note_revisions.down.sql"),
+    },
+    Migration {
+        version: 4,
+        name: "versions",
+        up: include_str!("../migrations/0004_versions.up.sql"),
+        down: include_str!("../migrations/0004_versions.down.sql"),
+    },
+    Migration {
+        version: 5,
+        name: "tags",
+        up: include_str!("../migrations/0005_tags.up.sql"),
+        down: include_str!("../migrations/0005_tags.down.sql"),
+    },
+    Migration {
+        version: 6,
+        name: "nested_categories",
+        up: include_str!("../migrations/0006_nested_categories.up.sql"),
+        down: include_str!("../migrations/0006_nested_categories.down.sql"),
+    },
+    Migration {
+        version: 7,
+        name: "triage_rules",
+        up: include_str!("../migrations/0007_triage_rules.up.sql"),
+        down: include_str!("../migrations/0007_triage_rules.down.sql"),
+    },
+    Migration {
+        version: 8,
+        name: "bin_retention",
+        up: include_str!("../migrations/0008_bin_retention.up.sql"),
+        down: include_str!("../migrations/0008_bin_retention.down.sql"),
+    },
+    Migration {
+        version: 9,
+        name: "invites",
+        up: include_str!("../migrations/0009_invites.up.sql"),
+        down: include_str!("../migrations/0009_invites.down.sql"),
+    },
+    Migration {
+        version: 10,
+        name: "user_roles",
+        up: include_str!("../migrations/0010_user_roles.up.sql"),
+        down: include_str!("../migrations/0010_user_roles.down.sql"),
+    },
+    Migration {
+        version: 11,
+        name: "password_resets",
+        up: include_str!("../migrations/0011_password_resets.up.sql"),
+        down: include_str!("../migrations/0011_password_resets.down.sql"),
+    },
+    Migration {
+        version: 12,
+        name: "sessions",
+        up: include_str!("../migrations/0012_sessions.up.sql"),
+        down: include_str!("../migrations/0012_sessions.down.sql"),
+    },
+    Migration {
+        version: 13,
+        name: "api_tokens",
+        up: include_str!("../migrations/0013_api_tokens.up.sql"),
+        down: include_str!("../migrations/0013_api_tokens.down.sql"),
+    },
+    Migration {
+        version: 14,
+        name: "failed_logins",
+        up: include_str!("../migrations/0014_failed_logins.up.sql"),
+        down: include_str!("../migrations/0014_failed_logins.down.sql"),
+    },
+    Migration {
+        version: 15,
+        name: "totp",
+        up: include_str!("../migrations/0015_totp.up.sql"),
+        down: include_str!("../migrations/0015_totp.down.sql"),
+    },
+];
+
+// key for the advisory lock that stops two servers from migrating the same
+// database at the same time
+//
+const LOCK_KEY: i64 = 0x6d65_6d6f;
+
+pub struct Migration {
+    pub version: i64,
+    pub name: &'static str,
+    up: &'static str,
+    down: &'static str,
+}
+
+pub struct MigrationStatus {
+    pub version: i64,
+    pub name: &'static str,
+    pub applied_at: Option<DateTime<Utc>>,
+}
+
+pub async fn status(db_pool: &Pool) -> Result<Vec<MigrationStatus>> {
+    let client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    create_table(&client).await?;
+
+    let applied = applied(client.query(APPLIED, &[]).await?);
+    known(&applied)?;
+
+    Ok(MIGRATIONS
+        .iter()
+        .map(|m| MigrationStatus {
+            version: m.version,
+            name: m.name,
+            applied_at: applied
+                .iter()
+                .find(|(version, _)| *version == m.version)
+                .map(|(_, applied_at)| *applied_at),
+        })
+        .collect())
+}
+
+pub async fn pending(db_pool: &Pool) -> Result<Vec<&'static Migration>> {
+    let client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    create_table(&client).await?;
+
+    let applied = applied(client.query(APPLIED, &[]).await?);
+    pending_after(&applied)
+}
+
+// applies every pending migration in order, each one in its own transaction
+//
+pub async fn up(db_pool: &Pool) -> Result<Vec<&'static Migration>> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    create_table(&client).await?;
+
+    let mut migrated = vec![];
+    loop {
+        let tx = client.transaction().await?;
+        let applied = lock(&tx).await?;
+
+        let migration = match pending_after(&applied)?.first() {
+            Some(migration) => *migration,
+            None => break,
+        };
+
+        info!(
+            "applying migration {} {}",
+            migration.version, migration.name
+        );
+        tx.batch_execute(migration.up).await?;
+
+        tx.execute(
+            "INSERT INTO schema_migrations(version, name) VALUES ($1, $2)",
+            &[&migration.version, &migration.name],
+        )
+        .await?;
+        tx.commit().await?;
+
+        migrated.push(migration);
+    }
+
+    Ok(migrated)
+}
+
+// reverts the most recently applied migration
+//
+pub async fn down(db_pool: &Pool) -> Result<Option<&'static Migration>> {
+    let mut client: Client = db_pool.get().await.map_err(Error::DeadPool)?;
+    create_table(&client).await?;
+
+    let tx = client.transaction().await?;
+    let applied = lock(&tx).await?;
+    known(&applied)?;
+
+    let migration = match applied.last() {
+        Some((version, _)) => MIGRATIONS
+            .iter()
+            .find(|m| m.version == *version)
+            .ok_or(Error::NotFound)?,
+        None => return Ok(None),
+    };
+
+    info!(
+        "reverting migration {} {}",
+        migration.version, migration.name
+    );
+    tx.batch_execute(migration.down).await?;
+    tx.execute(
+        "DELETE FROM schema_migrations WHERE version = $1",
+        &[&migration.version],
+    )
+    .await?;
+    tx.commit().await?;
+
+    Ok(Some(migration))
+}
+
+const APPLIED: &str = "SELECT version, applied_at FROM schema_migrations ORDER BY version";
+
+async fn create_table(client: &Client) -> Result<()> {
+    client
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                 version BIGINT PRIMARY KEY,
+                 name TEXT NOT NULL,
+                 applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+             )",
+        )
+        .await?;
+    Ok(())
+}
+
+// takes the migration lock for the rest of the transaction and returns the
+// migrations that have been applied
+//
+async fn lock(tx: &Transaction<'_>) -> Result<Vec<(i64, DateTime<Utc>)>> {
+    tx.execute("SELECT pg_advisory_xact_lock($1)", &[&LOCK_KEY])
+        .await?;
+    Ok(applied(tx.query(APPLIED, &[]).await?))
+}
+
+fn applied(rows: Vec<Row>) -> Vec<(i64, DateTime<Utc>)> {
+    rows.iter().map(|row| (row.get(0), row.get(1))).collect()
+}
+
+// a database that has been migrated by a newer server can't safely be used
+//
+fn known(applied: &[(i64, DateTime<Utc>)]) -> Result<()> {
+    match applied
+        .iter()
+        .find(|(version, _)| !MIGRATIONS.iter().any(|m| m.version == *version))
+    {
+        Some((version, _)) => Err(Error::Configuration(format!(
+            "database has migration {} which this server doesn't know about",
+            version
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn pending_after(applied: &[(i64, DateTime<Utc>)]) -> Result<Vec<&'static Migration>> {
+    known(applied)?;
+
+    Ok(MIGRATIONS
+        .iter()
+        .filter(|m| !applied.iter().any(|(version, _)| *version == m.version))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_versions_increase() {
+        for pair in MIGRATIONS.windows(2) {
+            assert!(pair[0].version < pair[1].version);
+        }
+        assert!(MIGRATIONS
+            .iter()
+            .all(|m| !m.up.trim().is_empty() && !m.down.trim().is_empty()));
+    }
+
+    #[test]
+    fn test_pending_migrations() {
+        let now = Utc::now();
+
+        assert_eq!(pending_after(&[]).unwrap().len(), MIGRATIONS.len());
+        assert!(pending_after(&[(1, now)])
+            .unwrap()
+            .iter()
+            .all(|m| m.version > 1));
+        assert!(pending_after(&[(1, now), (9999, now)]).is_err());
+    }
+}
@@ -0,0 +1,57 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+use crate::db::notes as db;
+use crate::db::{sessions, users};
+use crate::session;
+use actix_rt::time::interval;
+use deadpool_postgres::Pool;
+use std::time::Duration;
+use tracing::{error, info};
+
+const PURGE_INTERVAL_SECS: u64 = 60 * 60;
+const FAILED_LOGIN_DAYS: i32 = 30;
+
+// permanently deletes notes that have been in the bin for longer than their
+// owner's retention period (or the server's default if they haven't set one)
+// along with sessions that have been idle for too long to be used again
+// and failed logins older than FAILED_LOGIN_DAYS
+//
+pub async fn purge_periodically(db_pool: Pool, default_retention_days: i32) {
+    let mut timer = interval(Duration::from_secs(PURGE_INTERVAL_SECS));
+
+    loop {
+        timer.tick().await;
+
+        match db::purge_expired_bin(&db_pool, default_retention_days).await {
+            Ok(0) => {}
+            Ok(purged) => info!("purged {} notes from the bin", purged),
+            Err(e) => error!("unable to purge the bin: {}", e),
+        }
+
+        match sessions::purge_idle(&db_pool, session::IDLE_DAYS).await {
+            Ok(0) => {}
+            Ok(purged) => info!("purged {} idle sessions", purged),
+            Err(e) => error!("unable to purge idle sessions: {}", e),
+        }
+
+        match users::purge_failed_logins(&db_pool, FAILED_LOGIN_DAYS).await {
+            Ok(0) => {}
+            Ok(purged) => info!("purged {} failed logins", purged),
+            Err(e) => error!("unable to purge failed logins: {}", e),
+        }
+    }
+}
@@ -15,23 +15,185 @@
 // You should have received a copy of the GNU Affero General Public License
 // along with this program.  If not, see <https://www.gnu.org/licenses/>.
 
+use crate::api;
+use crate::db::sessions as db;
+use crate::db::{tokens, users};
 use crate::error::{Error, Result};
+use crate::handler::random_token;
+use crate::interop::tokens::TokenScope;
+use crate::interop::users::UserRole;
 use crate::interop::Key;
-use std::str::FromStr;
+use crate::ServerConfig;
+use actix_web::http::{header, Method};
+use actix_web::web::Data;
+use actix_web::HttpRequest;
+use deadpool_postgres::Pool;
 
+// the cookie only holds a random token, everything else about the session
+// lives in the sessions table so that it can be listed and revoked
+//
 const AUTH: &str = "auth";
 
-pub fn user_id(session: &actix_session::Session) -> Result<Key> {
-    if let Some(auth) = session.get::<String>(AUTH)? {
-        let user_id: Key = Key::from_str(&auth)?;
-        Ok(user_id)
+// set once the password has been accepted for a user with 2FA enabled, this
+// is not a logged in session until the second factor has been given
+//
+const PENDING_LOGIN: &str = "pending_login";
+const PENDING_LOGIN_SECS: i64 = 5 * 60;
+
+// sessions that haven't been used for this long are no longer valid
+pub const IDLE_DAYS: i32 = 30;
+
+pub struct Current {
+    pub id: Key,
+    pub user_id: Key,
+}
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct PendingLogin {
+    pub user_id: Key,
+    pub email: String,
+    expires_at: i64,
+}
+
+pub async fn current(session: &actix_session::Session, db_pool: &Pool) -> Result<Current> {
+    if let Some(token) = session.get::<String>(AUTH)? {
+        match db::touch(db_pool, &token, IDLE_DAYS).await {
+            Ok((id, user_id)) => Ok(Current { id, user_id }),
+            Err(Error::NotFound) => Err(Error::Authenticating),
+            Err(e) => Err(e),
+        }
     } else {
         Err(Error::Authenticating)
     }
 }
 
-pub fn save_user_id(session: &actix_session::Session, id: Key) -> Result<()> {
-    session.set(AUTH, format!("{}", id))?;
+// a request with an Authorization: Bearer header is authenticated by that
+// api token rather than the session cookie, the token's scope has to permit
+// the request. account management (password, sessions, tokens, admin) should
+// use current() instead which only accepts the cookie
+//
+pub async fn user_id(
+    req: &HttpRequest,
+    session: &actix_session::Session,
+    db_pool: &Pool,
+) -> Result<Key> {
+    if let Some(secret) = bearer_token(req)? {
+        let (user_id, scope) = match tokens::touch(db_pool, secret).await {
+            Ok(owner) => owner,
+            Err(Error::NotFound) => return Err(Error::Authenticating),
+            Err(e) => return Err(e),
+        };
+        if scope_permits(scope, req) {
+            Ok(user_id)
+        } else {
+            Err(Error::Forbidden)
+        }
+    } else {
+        Ok(current(session, db_pool).await?.user_id)
+    }
+}
+
+fn bearer_token(req: &HttpRequest) -> Result<Option<&str>> {
+    match req.headers().get(header::AUTHORIZATION) {
+        Some(value) => {
+            let value = value.to_str().map_err(|_| Error::Authenticating)?;
+            match value.strip_prefix("Bearer ") {
+                Some(secret) => Ok(Some(secret.trim())),
+                None => Err(Error::Authenticating),
+            }
+        }
+        None => Ok(None),
+    }
+}
+
+fn scope_permits(scope: TokenScope, req: &HttpRequest) -> bool {
+    match scope {
+        TokenScope::ReadWrite => true,
+        TokenScope::Read => req.method() == Method::GET || req.method() == Method::HEAD,
+        TokenScope::Bookmark => {
+            req.method() == Method::POST
+                && req.path() == format!("{}{}", api::MOUNT_POINT, api::BOOKMARK_PATH)
+        }
+    }
+}
+
+// the role is read from the database on every call so that demoting or
+// disabling an admin takes effect immediately
+//
+pub async fn admin_id(session: &actix_session::Session, db_pool: &Pool) -> Result<Key> {
+    let user_id = current(session, db_pool).await?.user_id;
+    match users::role(db_pool, user_id).await {
+        Ok(UserRole::Admin) => Ok(user_id),
+        Ok(UserRole::User) | Err(Error::NotFound) => Err(Error::Forbidden),
+        Err(e) => Err(e),
+    }
+}
+
+pub async fn start(
+    session: &actix_session::Session,
+    db_pool: &Pool,
+    req: &HttpRequest,
+    user_id: Key,
+) -> Result<()> {
+    let token = random_token(32);
+    let user_agent = req
+        .headers()
+        .get(header::USER_AGENT)
+        .and_then(|ua| ua.to_str().ok());
+    let ip_address = client_ip(req);
+
+    db::create(db_pool, &token, user_id, user_agent, ip_address.as_deref()).await?;
+
+    session.renew();
+    session.remove(PENDING_LOGIN);
+    session.set(AUTH, token)?;
+
+    Ok(())
+}
+
+pub fn start_pending(session: &actix_session::Session, user_id: Key, email: &str) -> Result<()> {
+    session.renew();
+    session.remove(AUTH);
+    session.set(
+        PENDING_LOGIN,
+        PendingLogin {
+            user_id,
+            email: String::from(email),
+            expires_at: chrono::Utc::now().timestamp() + PENDING_LOGIN_SECS,
+        },
+    )?;
 
     Ok(())
 }
+
+pub fn pending(session: &actix_session::Session) -> Result<PendingLogin> {
+    match session.get::<PendingLogin>(PENDING_LOGIN)? {
+        Some(pending) if pending.expires_at > chrono::Utc::now().timestamp() => Ok(pending),
+        _ => Err(Error::Authenticating),
+    }
+}
+
+pub async fn end(session: &actix_session::Session, db_pool: &Pool) -> Result<()> {
+    if let Some(token) = session.get::<String>(AUTH)? {
+        db::delete_by_token(db_pool, &token).await?;
+    }
+    session.purge();
+
+    Ok(())
+}
+
+// the Forwarded and X-Forwarded-For headers are only used when the server
+// is configured to trust them, otherwise any client could pick its own
+// address. connections on the unix socket have no address of their own
+//
+pub fn client_ip(req: &HttpRequest) -> Option<String> {
+    let trust_proxy_headers = req
+        .app_data::<Data<ServerConfig>>()
+        .is_some_and(|config| config.trust_proxy_headers);
+
+    if trust_proxy_headers {
+        req.connection_info().realip_remote_addr().map(String::from)
+    } else {
+        req.peer_addr().map(|addr| addr.ip().to_string())
+    }
+}
@@ -0,0 +1,81 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// search snippets come back from ts_headline with each match wrapped in
+// START and STOP. they're turned into plain text and the ranges of the
+// matches, so clients never have to render note content as html
+//
+// both markers are removed from the note before it's highlighted, so every
+// one left in ts_headline's output was put there by ts_headline
+//
+pub const START: char = '\u{2}';
+pub const STOP: char = '\u{3}';
+
+#[derive(Debug, PartialEq, serde::Serialize)]
+pub struct Snippet {
+    pub text: String,
+    // start (inclusive) and end (exclusive) of each match, counted in UTF-16
+    // code units of text as that's how javascript indexes strings
+    pub highlights: Vec<(usize, usize)>,
+}
+
+pub fn parse(marked: &str) -> Snippet {
+    let mut text = String::with_capacity(marked.len());
+    let mut highlights = vec![];
+    let mut len = 0;
+    let mut start = None;
+
+    for c in marked.chars() {
+        match c {
+            START => start = Some(len),
+            STOP => {
+                if let Some(start) = start.take() {
+                    highlights.push((start, len));
+                }
+            }
+            _ => {
+                text.push(c);
+                len += c.len_utf16();
+            }
+        }
+    }
+
+    Snippet { text, highlights }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let snippet = parse("the \u{2}cats\u{3} sat on the \u{2}cat\u{3}");
+        assert_eq!(snippet.text, "the cats sat on the cat");
+        assert_eq!(snippet.highlights, vec![(4, 8), (20, 23)]);
+
+        let snippet = parse("<script>é \u{2}cat\u{3}</script>");
+        assert_eq!(snippet.text, "<script>é cat</script>");
+        assert_eq!(snippet.highlights, vec![(10, 13)]);
+
+        // outside the basic multilingual plane a character is two code units
+        let snippet = parse("🐈 \u{2}cat\u{3}");
+        assert_eq!(snippet.text, "🐈 cat");
+        assert_eq!(snippet.highlights, vec![(3, 6)]);
+
+        assert_eq!(parse("no matches").highlights, vec![]);
+    }
+}
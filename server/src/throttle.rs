@@ -0,0 +1,246 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// in-memory tracking of failed logins, keyed by both the account's email and
+// the client's ip address. after a number of free attempts each further
+// failure doubles how long the key is locked out for
+//
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const ACCOUNT_FREE_ATTEMPTS: u32 = 5;
+// generous since many people can share an address
+const IP_FREE_ATTEMPTS: u32 = 20;
+
+const BASE_LOCKOUT: Duration = Duration::from_secs(1);
+pub const MAX_LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+// a key that hasn't failed for this long starts again from zero
+const FORGET_AFTER: Duration = Duration::from_secs(24 * 60 * 60);
+// only bother pruning forgotten keys once there are this many of them
+const PRUNE_THRESHOLD: usize = 10_000;
+
+struct Failures {
+    count: u32,
+    last: Instant,
+}
+
+impl Failures {
+    fn locked_until(&self, free_attempts: u32) -> Option<Instant> {
+        if self.count < free_attempts {
+            return None;
+        }
+        let doublings = (self.count - free_attempts).min(16);
+        let lockout = (BASE_LOCKOUT * 2u32.pow(doublings)).min(MAX_LOCKOUT);
+        Some(self.last + lockout)
+    }
+}
+
+#[derive(Default)]
+pub struct LoginThrottle {
+    failures: Mutex<HashMap<String, Failures>>,
+}
+
+fn account_key(email: &str) -> String {
+    format!("account:{}", email.trim().to_lowercase())
+}
+
+fn ip_key(ip_address: &str) -> String {
+    format!("ip:{}", ip_address)
+}
+
+fn keys(email: &str, ip_address: Option<&str>) -> Vec<(String, u32)> {
+    let mut keys = vec![(account_key(email), ACCOUNT_FREE_ATTEMPTS)];
+    if let Some(ip_address) = ip_address {
+        keys.push((ip_key(ip_address), IP_FREE_ATTEMPTS));
+    }
+    keys
+}
+
+impl LoginThrottle {
+    pub fn new() -> LoginThrottle {
+        Default::default()
+    }
+
+    // returns how long the caller has to wait if either the account or the
+    // ip address is currently locked out
+    //
+    pub fn check(&self, email: &str, ip_address: Option<&str>, now: Instant) -> Option<Duration> {
+        let failures = self.failures.lock().unwrap();
+
+        keys(email, ip_address)
+            .iter()
+            .filter_map(|(key, free_attempts)| failures.get(key)?.locked_until(*free_attempts))
+            .filter(|until| *until > now)
+            .max()
+            .map(|until| until - now)
+    }
+
+    pub fn record_failure(&self, email: &str, ip_address: Option<&str>, now: Instant) {
+        let mut failures = self.failures.lock().unwrap();
+
+        if failures.len() > PRUNE_THRESHOLD {
+            failures.retain(|_, f| now.duration_since(f.last) < FORGET_AFTER);
+        }
+
+        for (key, _) in keys(email, ip_address) {
+            let entry = failures.entry(key).or_insert(Failures {
+                count: 0,
+                last: now,
+            });
+            if now.duration_since(entry.last) >= FORGET_AFTER {
+                entry.count = 0;
+            }
+            entry.count += 1;
+            entry.last = now;
+        }
+    }
+
+    // only the account is reset, otherwise logging into an attacker
+    // controlled account would reset the count for their address
+    //
+    pub fn record_success(&self, email: &str) {
+        let mut failures = self.failures.lock().unwrap();
+        failures.remove(&account_key(email));
+    }
+}
+
+// password reset requests are limited the same way, every request counts
+// against both the email and the address. it's separate from the login
+// throttle so that requesting resets for someone can't lock them out
+//
+#[derive(Default)]
+pub struct ResetThrottle {
+    requests: LoginThrottle,
+}
+
+impl ResetThrottle {
+    pub fn new() -> ResetThrottle {
+        Default::default()
+    }
+
+    // returns how long the caller has to wait if the request isn't allowed
+    //
+    pub fn request(&self, email: &str, ip_address: Option<&str>, now: Instant) -> Option<Duration> {
+        let retry_after = self.requests.check(email, ip_address, now);
+        if retry_after.is_none() {
+            self.requests.record_failure(email, ip_address, now);
+        }
+        retry_after
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fail(throttle: &LoginThrottle, email: &str, ip: &str, times: u32, now: Instant) {
+        for _ in 0..times {
+            throttle.record_failure(email, Some(ip), now);
+        }
+    }
+
+    #[test]
+    fn test_free_attempts() {
+        let throttle = LoginThrottle::new();
+        let now = Instant::now();
+
+        fail(
+            &throttle,
+            "a@b.c",
+            "1.2.3.4",
+            ACCOUNT_FREE_ATTEMPTS - 1,
+            now,
+        );
+        assert_eq!(throttle.check("a@b.c", Some("1.2.3.4"), now), None);
+
+        fail(&throttle, "a@b.c", "1.2.3.4", 1, now);
+        assert_eq!(
+            throttle.check("a@b.c", Some("1.2.3.4"), now),
+            Some(BASE_LOCKOUT)
+        );
+        // the account is locked regardless of where the attempt comes from
+        assert!(throttle.check("A@B.C", Some("5.6.7.8"), now).is_some());
+        assert_eq!(throttle.check("d@e.f", Some("1.2.3.4"), now), None);
+    }
+
+    #[test]
+    fn test_exponential_backoff() {
+        let throttle = LoginThrottle::new();
+        let now = Instant::now();
+
+        fail(
+            &throttle,
+            "a@b.c",
+            "1.2.3.4",
+            ACCOUNT_FREE_ATTEMPTS + 3,
+            now,
+        );
+        assert_eq!(throttle.check("a@b.c", None, now), Some(BASE_LOCKOUT * 8));
+        assert_eq!(throttle.check("a@b.c", None, now + BASE_LOCKOUT * 8), None);
+
+        fail(&throttle, "a@b.c", "1.2.3.4", 100, now);
+        assert_eq!(throttle.check("a@b.c", None, now), Some(MAX_LOCKOUT));
+    }
+
+    #[test]
+    fn test_ip_lockout() {
+        let throttle = LoginThrottle::new();
+        let now = Instant::now();
+
+        for i in 0..IP_FREE_ATTEMPTS {
+            throttle.record_failure(&format!("user{}@b.c", i), Some("1.2.3.4"), now);
+        }
+        assert!(throttle.check("new@b.c", Some("1.2.3.4"), now).is_some());
+        assert_eq!(throttle.check("new@b.c", Some("5.6.7.8"), now), None);
+    }
+
+    #[test]
+    fn test_success_resets_account_only() {
+        let throttle = LoginThrottle::new();
+        let now = Instant::now();
+
+        fail(&throttle, "a@b.c", "1.2.3.4", IP_FREE_ATTEMPTS, now);
+        throttle.record_success("a@b.c");
+        assert_eq!(throttle.check("a@b.c", None, now), None);
+        assert!(throttle.check("a@b.c", Some("1.2.3.4"), now).is_some());
+    }
+
+    #[test]
+    fn test_reset_requests() {
+        let throttle = ResetThrottle::new();
+        let now = Instant::now();
+
+        for _ in 0..ACCOUNT_FREE_ATTEMPTS {
+            assert_eq!(throttle.request("a@b.c", Some("1.2.3.4"), now), None);
+        }
+        assert!(throttle.request("a@b.c", Some("5.6.7.8"), now).is_some());
+        assert_eq!(throttle.request("d@e.f", Some("5.6.7.8"), now), None);
+    }
+
+    #[test]
+    fn test_failures_are_forgotten() {
+        let throttle = LoginThrottle::new();
+        let now = Instant::now();
+
+        fail(&throttle, "a@b.c", "1.2.3.4", ACCOUNT_FREE_ATTEMPTS, now);
+        let later = now + FORGET_AFTER;
+        fail(&throttle, "a@b.c", "1.2.3.4", 1, later);
+        assert_eq!(throttle.check("a@b.c", None, later), None);
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// the certificate and private key are read when the server starts and again
+// whenever it receives SIGHUP, so that a renewed certificate is picked up
+// without restarting. if reloading fails the previous certificate stays in use
+//
+use crate::error::{Error, Result};
+use rustls::internal::pemfile;
+use rustls::sign::{self, CertifiedKey};
+use rustls::{ClientHello, NoClientAuth, ResolvesServerCert, ServerConfig};
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use tokio::signal::unix::{signal, SignalKind};
+use tracing::{error, info};
+
+pub struct CertificateResolver {
+    certificate: PathBuf,
+    private_key: PathBuf,
+    current: RwLock<CertifiedKey>,
+}
+
+impl CertificateResolver {
+    pub fn new(certificate: &Path, private_key: &Path) -> Result<CertificateResolver> {
+        Ok(CertificateResolver {
+            certificate: PathBuf::from(certificate),
+            private_key: PathBuf::from(private_key),
+            current: RwLock::new(load(certificate, private_key)?),
+        })
+    }
+
+    pub fn reload(&self) -> Result<()> {
+        let certified_key = load(&self.certificate, &self.private_key)?;
+        match self.current.write() {
+            Ok(mut current) => *current = certified_key,
+            Err(_) => return Err(Error::Other),
+        }
+
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertificateResolver {
+    fn resolve(&self, _client_hello: ClientHello) -> Option<CertifiedKey> {
+        self.current.read().ok().map(|current| current.clone())
+    }
+}
+
+pub fn server_config(resolver: Arc<CertificateResolver>) -> ServerConfig {
+    let mut config = ServerConfig::new(NoClientAuth::new());
+    config.cert_resolver = resolver;
+
+    config
+}
+
+pub async fn reload_on_hangup(resolver: Arc<CertificateResolver>) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(e) => {
+            error!(
+                "unable to listen for SIGHUP, certificates won't be reloaded: {}",
+                e
+            );
+            return;
+        }
+    };
+
+    while hangups.recv().await.is_some() {
+        match resolver.reload() {
+            Ok(()) => info!("reloaded the TLS certificate"),
+            Err(e) => error!("unable to reload the TLS certificate: {}", e),
+        }
+    }
+}
+
+fn load(certificate: &Path, private_key: &Path) -> Result<CertifiedKey> {
+    let unreadable =
+        |path: &Path| Error::Configuration(format!("unable to read {}", path.display()));
+
+    let certificates = pemfile::certs(&mut BufReader::new(File::open(certificate)?))
+        .map_err(|_| unreadable(certificate))?;
+    if certificates.is_empty() {
+        return Err(unreadable(certificate));
+    }
+
+    // either a PKCS#8 or an RSA private key
+    let mut keys = pemfile::pkcs8_private_keys(&mut BufReader::new(File::open(private_key)?))
+        .map_err(|_| unreadable(private_key))?;
+    if keys.is_empty() {
+        keys = pemfile::rsa_private_keys(&mut BufReader::new(File::open(private_key)?))
+            .map_err(|_| unreadable(private_key))?;
+    }
+    let key = match keys.first() {
+        Some(key) => sign::any_supported_type(key).map_err(|_| unreadable(private_key))?,
+        None => return Err(unreadable(private_key)),
+    };
+
+    Ok(CertifiedKey::new(certificates, Arc::new(key)))
+}
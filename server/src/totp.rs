@@ -0,0 +1,164 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+// RFC 6238 time-based one time passwords with the parameters every
+// authenticator app defaults to: HMAC-SHA1, 6 digits and a 30 second step
+//
+use hmac::{Hmac, Mac, NewMac};
+use rand::{thread_rng, RngCore};
+use sha1::Sha1;
+
+const SECRET_SIZE: usize = 20;
+const STEP_SECS: i64 = 30;
+const DIGITS: u32 = 6;
+// accept codes from one step either side of now to allow for clock drift
+const DRIFT_STEPS: i64 = 1;
+
+const ISSUER: &str = "Memo";
+
+pub fn generate_secret() -> Vec<u8> {
+    let mut secret = vec![0; SECRET_SIZE];
+    thread_rng().fill_bytes(&mut secret);
+
+    secret
+}
+
+// RFC 4648 base32 without padding, which is how otpauth URIs carry secrets
+//
+pub fn base32(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+    let mut res = String::with_capacity((bytes.len() * 8).div_ceil(5));
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for byte in bytes {
+        buffer = (buffer << 8) | u32::from(*byte);
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            res.push(char::from(ALPHABET[((buffer >> bits) & 31) as usize]));
+        }
+    }
+    if bits > 0 {
+        res.push(char::from(ALPHABET[((buffer << (5 - bits)) & 31) as usize]));
+    }
+
+    res
+}
+
+pub fn otpauth_uri(secret: &[u8], account: &str) -> String {
+    format!(
+        "otpauth://totp/{issuer}:{account}?secret={secret}&issuer={issuer}&algorithm=SHA1&digits={digits}&period={period}",
+        issuer = ISSUER,
+        account = percent_encode(account),
+        secret = base32(secret),
+        digits = DIGITS,
+        period = STEP_SECS
+    )
+}
+
+fn percent_encode(s: &str) -> String {
+    s.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(b).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// RFC 4226 HOTP
+//
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_varkey(secret).expect("hmac accepts keys of any size");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0xf) as usize;
+    let truncated = (u32::from(hash[offset] & 0x7f) << 24)
+        | (u32::from(hash[offset + 1]) << 16)
+        | (u32::from(hash[offset + 2]) << 8)
+        | u32::from(hash[offset + 3]);
+
+    truncated % 10u32.pow(DIGITS)
+}
+
+pub fn step(unix_secs: i64) -> i64 {
+    unix_secs.div_euclid(STEP_SECS)
+}
+
+// returns the time step that the code matched so that the caller can refuse
+// to accept the same code twice
+//
+pub fn verify(secret: &[u8], code: &str, unix_secs: i64) -> Option<i64> {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+
+    let now = step(unix_secs);
+    (now - DRIFT_STEPS..=now + DRIFT_STEPS)
+        .filter(|s| *s >= 0)
+        .find(|s| hotp(secret, *s as u64) == code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_hotp_rfc4226_vectors() {
+        let expected = [
+            755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489,
+        ];
+        for (counter, code) in expected.iter().enumerate() {
+            assert_eq!(hotp(RFC_SECRET, counter as u64), *code);
+        }
+    }
+
+    #[test]
+    fn test_totp_rfc6238_vectors() {
+        // the RFC lists 8 digit codes, these are their last 6 digits
+        assert_eq!(verify(RFC_SECRET, "287082", 59), Some(1));
+        assert_eq!(verify(RFC_SECRET, "081804", 1111111109), Some(37037036));
+        assert_eq!(verify(RFC_SECRET, "050471", 1111111111), Some(37037037));
+        assert_eq!(verify(RFC_SECRET, "005924", 1234567890), Some(41152263));
+        assert_eq!(verify(RFC_SECRET, "279037", 2000000000), Some(66666666));
+    }
+
+    #[test]
+    fn test_verify_drift() {
+        let code = format!("{:06}", hotp(RFC_SECRET, 100));
+        assert_eq!(verify(RFC_SECRET, &code, 99 * STEP_SECS), Some(100));
+        assert_eq!(verify(RFC_SECRET, &code, 101 * STEP_SECS + 29), Some(100));
+        assert_eq!(verify(RFC_SECRET, &code, 102 * STEP_SECS), None);
+        assert_eq!(verify(RFC_SECRET, "12345", 0), None);
+        assert_eq!(verify(RFC_SECRET, "abcdef", 0), None);
+    }
+
+    #[test]
+    fn test_base32() {
+        assert_eq!(base32(b""), "");
+        assert_eq!(base32(b"f"), "MY");
+        assert_eq!(base32(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(base32(RFC_SECRET), "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ");
+    }
+}
@@ -0,0 +1,224 @@
+// Copyright (C) 2020 Inderjit Gill <email@indy.io>
+
+// This file is part of Memo
+
+// Memo is free software: you can redistribute it and/or modify
+// it under the terms of the GNU Affero General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+
+// Memo is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+// GNU Affero General Public License for more details.
+
+// You should have received a copy of the GNU Affero General Public License
+// along with this program.  If not, see <https://www.gnu.org/licenses/>.
+
+use crate::error::{Error, Result};
+use crate::interop::errors::FieldError;
+
+pub const MAX_USERNAME_LENGTH: usize = 64;
+// argon2 will hash anything, this just stops clients from making it hash megabytes
+pub const MAX_PASSWORD_LENGTH: usize = 1024;
+const MAX_EMAIL_LENGTH: usize = 254;
+
+// what clients are allowed to store, taken from the configuration
+//
+#[derive(Clone, Debug)]
+pub struct Limits {
+    // in characters
+    pub max_title_length: usize,
+    // in bytes
+    pub max_content_size: usize,
+    pub min_password_length: usize,
+    // how many of lowercase, uppercase, digits and symbols a password needs
+    pub password_character_classes: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Limits {
+        Limits {
+            max_title_length: 255,
+            max_content_size: 512 * 1024,
+            min_password_length: 8,
+            password_character_classes: 1,
+        }
+    }
+}
+
+pub trait Validate {
+    fn validate(&self, limits: &Limits) -> Result<()>;
+}
+
+// collects every problem with a payload so that they can all be reported at once,
+// only the first problem with each field is kept
+//
+#[derive(Default)]
+pub struct Checks {
+    problems: Vec<FieldError>,
+}
+
+impl Checks {
+    pub fn new() -> Checks {
+        Default::default()
+    }
+
+    pub fn not_blank(mut self, field: &'static str, value: &str) -> Checks {
+        if value.trim().is_empty() {
+            self.problem(field, String::from("can't be empty"));
+        }
+        self
+    }
+
+    pub fn max_length(mut self, field: &'static str, value: &str, max: usize) -> Checks {
+        if value.chars().count() > max {
+            self.problem(field, format!("can't be longer than {} characters", max));
+        }
+        self
+    }
+
+    pub fn max_size(mut self, field: &'static str, value: &str, max: usize) -> Checks {
+        if value.len() > max {
+            self.problem(field, format!("can't be larger than {} bytes", max));
+        }
+        self
+    }
+
+    pub fn email(mut self, field: &'static str, value: &str) -> Checks {
+        if !is_email(value) {
+            self.problem(field, String::from("isn't a valid email address"));
+        }
+        self
+    }
+
+    pub fn password(mut self, field: &'static str, value: &str, limits: &Limits) -> Checks {
+        if value.chars().count() < limits.min_password_length {
+            self.problem(
+                field,
+                format!(
+                    "has to be at least {} characters long",
+                    limits.min_password_length
+                ),
+            );
+        } else if value.len() > MAX_PASSWORD_LENGTH {
+            self.problem(
+                field,
+                format!("can't be larger than {} bytes", MAX_PASSWORD_LENGTH),
+            );
+        } else if character_classes(value) < limits.password_character_classes {
+            self.problem(
+                field,
+                format!(
+                    "has to contain {} of: lowercase letters, uppercase letters, digits, symbols",
+                    limits.password_character_classes
+                ),
+            );
+        }
+        self
+    }
+
+    pub fn finish(self) -> Result<()> {
+        if self.problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Validation(self.problems))
+        }
+    }
+
+    fn problem(&mut self, field: &'static str, message: String) {
+        if !self.problems.iter().any(|p| p.field == field) {
+            self.problems.push(FieldError { field, message });
+        }
+    }
+}
+
+// deliberately loose, the only real check is whether mail can be delivered
+//
+fn is_email(value: &str) -> bool {
+    if value.len() > MAX_EMAIL_LENGTH || value.chars().any(char::is_whitespace) {
+        return false;
+    }
+
+    let mut parts = value.split('@');
+    match (parts.next(), parts.next(), parts.next()) {
+        (Some(local), Some(domain), None) => {
+            !local.is_empty()
+                && domain.contains('.')
+                && !domain.starts_with('.')
+                && !domain.ends_with('.')
+                && !domain.contains("..")
+        }
+        _ => false,
+    }
+}
+
+fn character_classes(value: &str) -> usize {
+    let classes = [
+        value.chars().any(|c| c.is_lowercase()),
+        value.chars().any(|c| c.is_uppercase()),
+        value.chars().any(|c| c.is_numeric()),
+        value.chars().any(|c| !c.is_alphanumeric()),
+    ];
+    classes.iter().filter(|&&present| present).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(result: Result<()>) -> Vec<&'static str> {
+        match result {
+            Ok(()) => vec![],
+            Err(Error::Validation(problems)) => problems.iter().map(|p| p.field).collect(),
+            Err(e) => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn test_is_email() {
+        assert!(is_email("user@example.com"));
+        assert!(is_email("first.last+memo@mail.example.co.uk"));
+
+        assert!(!is_email(""));
+        assert!(!is_email("user"));
+        assert!(!is_email("@example.com"));
+        assert!(!is_email("user@localhost"));
+        assert!(!is_email("user@@example.com"));
+        assert!(!is_email("user@example..com"));
+        assert!(!is_email("us er@example.com"));
+    }
+
+    #[test]
+    fn test_password() {
+        let limits = Limits {
+            min_password_length: 8,
+            password_character_classes: 3,
+            ..Default::default()
+        };
+        let check = |password: &str| {
+            Checks::new()
+                .password("password", password, &limits)
+                .finish()
+        };
+
+        assert!(check("Correct horse 1").is_ok());
+        assert!(check("Sh0rt").is_err());
+        assert!(check("alllowercase").is_err());
+        assert!(check(&"Aa1".repeat(400)).is_err());
+    }
+
+    #[test]
+    fn test_checks() {
+        let result = Checks::new()
+            .not_blank("title", " ")
+            .max_length("title", "     ", 2)
+            .max_length("username", "ééé", 3)
+            .max_size("content", "ééé", 3)
+            .email("email", "nope")
+            .finish();
+
+        // the title is only reported once
+        assert_eq!(fields(result), vec!["title", "content", "email"]);
+    }
+}
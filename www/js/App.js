@@ -2,6 +2,7 @@ import { initialState, reducer } from '/js/AppState.js';
 import { html, Router, Route, Link, route } from '/lib/preact/mod.js';
 
 import Net from '/js/Net.js';
+import { flattenCategoryTree } from '/js/JsUtils.js';
 import { WasmInterfaceProvider }        from '/js/WasmInterfaceProvider.js';
 import { useStateValue, StateProvider } from '/js/StateProvider.js';
 import { augmentSettingsWithCssModifierParameters } from '/js/ColourCreator.js';
@@ -29,10 +30,10 @@ export async function buildInitialState() {
         user
       });
 
-      let categories = await Net.get("/api/categories");
+      let categoryTree = await Net.get("/api/categories");
       state = reducer(state, {
         type: 'categories-set',
-        categories
+        categories: flattenCategoryTree(categoryTree)
       });
 
       console.log('user is logged in');
@@ -66,8 +67,8 @@ function TopBarMenu(props) {
     let user = state.user;
     if (user) {
       status += user.username;
-      if (user.admin) {
-        status += ` (${user.admin.db_name})`;
+      if (user.role === 'admin') {
+        status += ' (admin)';
       }
     } else {
       status = 'Login';
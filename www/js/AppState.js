@@ -12,6 +12,12 @@ export const initialState = {
     notes: undefined,
     triaged: undefined,
     bin: undefined
+  },
+  // where the next page of each listing starts, undefined on the last page
+  listingCursor: {
+    notes: undefined,
+    triaged: undefined,
+    bin: undefined
   }
 };
 
@@ -75,7 +81,20 @@ export const reducer = (state, action) => {
 
       listing[action.resource] = action.listing;
 
-      return updateStateWithListing(state, listing);
+      return updateStateWithListingCursor(updateStateWithListing(state, listing),
+                                          action.resource, action.cursor);
+    }
+  case 'listing-appended':
+    {
+      let listing = getListingFromState(state);
+
+      // notes created or moved here since the first page may already be present
+      const existing = listing[action.resource] || [];
+      const appended = action.listing.filter(n => !findNote(existing, n.id));
+      listing[action.resource] = existing.concat(appended);
+
+      return updateStateWithListingCursor(updateStateWithListing(state, listing),
+                                          action.resource, action.cursor);
     }
   case 'listing-note-appended':
     {
@@ -175,7 +194,8 @@ export const reducer = (state, action) => {
 
       listing.bin = [];
 
-      return updateStateWithListing(state, listing);
+      return updateStateWithListingCursor(updateStateWithListing(state, listing),
+                                          'bin', undefined);
     }
   default:
     return state;
@@ -203,3 +223,13 @@ function updateStateWithListing(state, listing) {
 
   return newState;
 }
+
+function updateStateWithListingCursor(state, resource, cursor) {
+  return {
+    ...state,
+    listingCursor: {
+      ...state.listingCursor,
+      [resource]: cursor
+    }
+  };
+}
@@ -25,3 +25,10 @@ export function formattedDate(timestamp) {
 
   return textual;
 }
+
+// the server returns categories as a tree, the client keeps a flat list
+export function flattenCategoryTree(nodes) {
+  return nodes.reduce((categories, { children, ...category }) => {
+    return categories.concat([category], flattenCategoryTree(children));
+  }, []);
+}
@@ -18,7 +18,14 @@ function go(method, url, data) {
     options.body = JSON.stringify(data);
   }
 
-  return fetch(url, options).then(response => response.json());
+  return fetch(url, options).then(response => {
+    // e.g. a 409 Conflict carries the server's copy of a note in its body,
+    // that shouldn't be mistaken for the result of a successful request
+    if (!response.ok) {
+      return Promise.reject(response);
+    }
+    return response.json();
+  });
 }
 
 
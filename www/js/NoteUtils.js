@@ -28,19 +28,44 @@ export function ensureListingLoaded(resource, url) {
   }, []);
 }
 
+// listings are paginated, only the first page is fetched here. the cursor
+// for the next page is kept so that the user can ask for more (see LoadMore)
+//
 export async function fetchListing(dispatch, resource, url) {
-  const listing = await Net.get(url || `/api/${resource}`);
-  setListing(dispatch, resource, listing);
+  const page = await Net.get(listingUrl(resource, url));
+
+  setListing(dispatch, resource, page.items, page.next_cursor);
 }
 
-export function setListing(dispatch, resource, listing) {
+export async function fetchMoreListing(dispatch, resource, cursor, url) {
+  const page = await Net.get(listingUrl(resource, url, cursor));
+
+  dispatch({
+    type: 'listing-appended',
+    resource,
+    listing: page.items,
+    cursor: page.next_cursor
+  });
+}
+
+export function setListing(dispatch, resource, listing, cursor) {
   dispatch({
     type: 'listing-set',
     resource,
-    listing
+    listing,
+    cursor
   });
 }
 
+// the url may already have a query string (e.g. a tag filter)
+function listingUrl(resource, url, cursor) {
+  const u = new URL(url || `/api/${resource}`, window.location.origin);
+  if (cursor) {
+    u.searchParams.set('after', cursor);
+  }
+  return `${u.pathname}${u.search}`;
+}
+
 // simple parsing for urls and newlines only. A stupid regexp approach will do here,
 // but write an actual lexer+parser+compiler if other markup is required
 //
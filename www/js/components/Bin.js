@@ -6,6 +6,7 @@ import { useStateValue } from '/js/StateProvider.js';
 
 import BaseNote from '/js/components/BaseNote.js';
 import Card from '/js/components/Card.js';
+import LoadMore from '/js/components/LoadMore.js';
 
 function Bin() {
   const [state, dispatch] = useStateValue();
@@ -37,6 +38,7 @@ function Bin() {
       <div class="card-holder">
         ${ listing }
       </div>
+      <${LoadMore} resource="bin"/>
     </div>`;
 }
 
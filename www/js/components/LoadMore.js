@@ -0,0 +1,25 @@
+import { html } from '/lib/preact/mod.js';
+
+import { fetchMoreListing } from '/js/NoteUtils.js';
+import { useStateValue } from '/js/StateProvider.js';
+
+// shown underneath a listing for as long as there are more pages to fetch
+//
+export default function LoadMore({ resource, url }) {
+  const [state, dispatch] = useStateValue();
+
+  const cursor = state.listingCursor[resource];
+  if (!cursor) {
+    return null;
+  }
+
+  function onLoadMoreClicked(e) {
+    e.preventDefault();
+    fetchMoreListing(dispatch, resource, cursor, url);
+  }
+
+  return html`
+    <div class="pad-left-1rem pad-top-1rem">
+      <h2 class="button" onClick=${ onLoadMoreClicked }>Load More</h2>
+    </div>`;
+}
@@ -44,8 +44,19 @@ function Login() {
     Net.post('api/auth', {
       email: state['login-email'],
       password: state['login-password']
-    }).then(user => {
-      loginCallback(user);
+    }).then(response => {
+      if (response.two_factor_required) {
+        const code = window.prompt("Authenticator code (or a recovery code):");
+        if (code) {
+          // recovery codes are 10 hex characters, authenticator codes are 6 digits
+          const secondFactor = /^\s*\d{6}\s*$/.test(code) ? { code } : { recovery_code: code };
+          return Net.post('api/auth/2fa', secondFactor).then(user => {
+            loginCallback(user);
+          });
+        }
+      } else {
+        loginCallback(response);
+      }
     });
   };
 
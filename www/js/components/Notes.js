@@ -7,6 +7,7 @@ import { useStateValue } from '/js/StateProvider.js';
 
 import BaseNote from '/js/components/BaseNote.js';
 import Card from '/js/components/Card.js';
+import LoadMore from '/js/components/LoadMore.js';
 
 function Notes() {
   const [state, dispatch] = useStateValue();
@@ -29,6 +30,7 @@ function Notes() {
       <div class="card-holder">
         ${ listing }
       </div>
+      <${LoadMore} resource="notes"/>
     </div>`;
 }
 
@@ -152,10 +154,13 @@ function CreateNoteForm() {
     const protoNote = noteFromText(userText);
     if (protoNote) {
       Net.post(`/api/notes`, protoNote).then(note => {
-        dispatch({
-          type: 'listing-note-appended',
-          note
-        });
+        // the triage rules may have already moved the note out of the inbox
+        if (!note.triaged_at) {
+          dispatch({
+            type: 'listing-note-appended',
+            note
+          });
+        }
       });
       setUserText('');
     }
@@ -1,12 +1,14 @@
 import { html, useState } from '/lib/preact/mod.js';
 
 import Net from '/js/Net.js';
+import { flattenCategoryTree } from '/js/JsUtils.js';
 import { ensureListingLoaded, notePigment } from '/js/NoteUtils.js';
 import { svgBin, svgExpand, svgMinimise } from '/js/svgIcons.js';
 import { useStateValue } from '/js/StateProvider.js';
 
 import BaseNote from '/js/components/BaseNote.js';
 import Card from '/js/components/Card.js';
+import LoadMore from '/js/components/LoadMore.js';
 
 function TriagedNotes() {
   const [state, dispatch] = useStateValue();
@@ -47,6 +49,7 @@ function TriagedNotes() {
     return html`<div>
                   <div class="section-controls-headroom"></div>
                   <div>${ triagedSectionsHtml }</div>
+                  <${LoadMore} resource="triaged"/>
                   <div class="hr"/>
                   <div class="section-controls">
                     <${NewCategoryForm }/>
@@ -128,10 +131,10 @@ function NewCategoryForm() {
   function newCategorySubmit(e) {
     e.preventDefault();
 
-    Net.post(`/api/categories`, { title: localState.text }).then(latestCategories => {
+    Net.post(`/api/categories`, { title: localState.text }).then(categoryTree => {
       dispatch({
         type: 'categories-set',
-        categories: latestCategories
+        categories: flattenCategoryTree(categoryTree)
       });
       setLocalState({...localState, text: ''});
     });